@@ -0,0 +1,76 @@
+//! Time the interpreter. Run with `cargo bench`, adding `--features uncached`
+//! to compare with the interpreter without the decoded instruction cache.
+
+extern crate intcode;
+
+use intcode::{Int, Options, Program};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::time::{Duration, Instant};
+
+fn load(name: &str) -> Program {
+    let f = File::open(format!["input/{}.int", name]).expect("missing input");
+    Program::new(io::BufReader::new(f))
+}
+
+/// Time f over iterations runs of prog with options, returning the time per
+/// run and the result of the last.
+fn time<F: Fn(&mut Program) -> Int>(
+    prog: &mut Program,
+    options: Options,
+    iterations: u32,
+    f: &F,
+) -> (Duration, Int) {
+    prog.set_options(options);
+    let mut result = 0;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        prog.reset();
+        result = f(prog);
+    }
+    (start.elapsed() / iterations, result)
+}
+
+/// Time f over iterations runs of prog with caching enabled and, with the
+/// uncached feature, disabled.
+fn bench<F: Fn(&mut Program) -> Int>(name: &str, mut prog: Program, iterations: u32, f: F) {
+    let (cached, result) = time(&mut prog, Options::default(), iterations, &f);
+    print!["{:<16} cached {:>10.3?}", name, cached];
+    #[cfg(feature = "uncached")]
+    {
+        let options = Options {
+            uncached: true,
+            ..Options::default()
+        };
+        let (uncached, uncached_result) = time(&mut prog, options, iterations, &f);
+        assert_eq!(result, uncached_result, "{}: results differ", name);
+        print![
+            "  uncached {:>10.3?}  speedup {:.2}x",
+            uncached,
+            uncached.as_secs_f64() / cached.as_secs_f64()
+        ];
+    }
+    println!["  result {}", result];
+}
+
+fn main() {
+    bench("day9 part 2", load("day9"), 10, |p| {
+        let mut output = Vec::new();
+        p.exe(None, VecDeque::from(vec![2]), &mut output).unwrap();
+        output[0]
+    });
+    bench("day19 sweep", load("day19"), 3, |p| {
+        let mut affected = 0;
+        for y in 0..50 {
+            for x in 0..50 {
+                p.reset();
+                let mut output = Vec::new();
+                p.exe(None, VecDeque::from(vec![x, y]), &mut output)
+                    .unwrap();
+                affected += output[0];
+            }
+        }
+        affected
+    });
+}
@@ -0,0 +1,41 @@
+//! Compare a program compiled by `intcode compile` with the interpreter. Run
+//! with `cargo bench -p intcode-compiled`.
+
+extern crate intcode;
+
+use intcode_compiled::{day9, load};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Time day 9 part 2 compiled against the cached interpreter.
+fn bench_compiled(iterations: u32) {
+    let mut prog = load("day9");
+    let mut times = [Duration::default(); 2];
+    let mut results = [Vec::new(), Vec::new()];
+    for (i, compiled) in [false, true].iter().enumerate() {
+        let start = Instant::now();
+        for _ in 0..iterations {
+            prog.reset();
+            results[i].clear();
+            if *compiled {
+                day9::run(&mut prog, &mut VecDeque::from(vec![2]), &mut results[i]).unwrap();
+            } else {
+                prog.exe(None, VecDeque::from(vec![2]), &mut results[i])
+                    .unwrap();
+            }
+        }
+        times[i] = start.elapsed() / iterations;
+    }
+    assert_eq!(results[0], results[1], "compiled results differ");
+    println![
+        "{:<16} compiled {:>8.3?}  cached {:>10.3?}  speedup {:.2}x",
+        "day9 part 2",
+        times[1],
+        times[0],
+        times[0].as_secs_f64() / times[1].as_secs_f64()
+    ];
+}
+
+fn main() {
+    bench_compiled(10);
+}
@@ -0,0 +1,34 @@
+//! Compile each program in ../input to a module in OUT_DIR, which src/lib.rs
+//! includes.
+
+use intcode::compile::compile;
+use intcode::{Int, Program};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("../input");
+    println!["cargo:rerun-if-changed={}", input.display()];
+    let out = Path::new(&env::var_os("OUT_DIR").unwrap()).join("compiled");
+    fs::create_dir_all(&out).unwrap();
+    for entry in fs::read_dir(&input).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|e| e != "int") {
+            continue;
+        }
+        let code: Vec<Int> = fs::read_to_string(&path)
+            .unwrap()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse()
+                    .unwrap_or_else(|e| panic!["{}: {:?}: {}", path.display(), s, e])
+            })
+            .collect();
+        let prog = Program::from_code(code);
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        fs::write(out.join(format!["{}.rs", name]), compile(&prog, "run")).unwrap();
+    }
+}
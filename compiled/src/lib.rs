@@ -0,0 +1,29 @@
+//! The programs in ../input compiled to Rust by build.rs, for the tests and
+//! benchmark comparing them with the interpreter. Each module defines a `run`
+//! function as documented by `intcode::compile::compile`.
+
+use intcode::Program;
+use std::fs::File;
+use std::io;
+
+/// Declare a module for each compiled program.
+macro_rules! compiled {
+    ($($name:ident),*) => {$(
+        #[allow(clippy::all)]
+        pub mod $name {
+            include!(concat!(env!("OUT_DIR"), "/compiled/", stringify!($name), ".rs"));
+        }
+    )*};
+}
+
+compiled!(day2, day5, day7, day9, day11, day13, day15, day17, day19);
+
+/// Load the program that was compiled as module name.
+pub fn load(name: &str) -> Program {
+    let path = format![
+        concat!(env!("CARGO_MANIFEST_DIR"), "/../input/{}.int"),
+        name
+    ];
+    let f = File::open(&path).unwrap_or_else(|e| panic!["{}: {}", path, e]);
+    Program::new(io::BufReader::new(f))
+}
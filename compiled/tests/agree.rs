@@ -0,0 +1,99 @@
+//! Programs compiled by `intcode compile` must agree with the interpreter.
+
+extern crate intcode;
+
+use intcode::{Int, IntcodeError, Program};
+use intcode_compiled::{day11, day13, day15, day17, day19, day2, day5, day7, day9, load};
+use std::collections::VecDeque;
+
+type Compiled = fn(&mut Program, &mut VecDeque<Int>, &mut Vec<Int>) -> Result<(), IntcodeError>;
+
+/// Run prog with the interpreter and the compiled function on the same
+/// input, after applying patches to memory, and check that the results,
+/// output and memory agree.
+fn agree(name: &str, run: Compiled, patches: &[(Int, Int)], input: &[Int]) {
+    let mut interpreted = load(name);
+    for (addr, value) in patches {
+        interpreted.poke(*addr, *value).unwrap();
+    }
+    let mut compiled = interpreted.clone();
+
+    let mut output = Vec::new();
+    let expected = interpreted
+        .exe(None, VecDeque::from(input.to_vec()), &mut output)
+        .map(|_| ())
+        .map_err(|e| e.to_string());
+    let mut compiled_input = VecDeque::from(input.to_vec());
+    let mut compiled_output = Vec::new();
+    let result =
+        run(&mut compiled, &mut compiled_input, &mut compiled_output).map_err(|e| e.to_string());
+
+    assert_eq!(result, expected, "{}: results differ", name);
+    assert_eq!(compiled_output, output, "{}: output differs", name);
+    assert_eq!(compiled.ip(), interpreted.ip(), "{}: ip differs", name);
+    assert_eq!(compiled.rel_base(), interpreted.rel_base());
+    assert_eq!(compiled.is_halted(), interpreted.is_halted());
+    assert_eq!(
+        compiled.to_string(),
+        interpreted.to_string(),
+        "{}: memory differs",
+        name
+    );
+}
+
+#[test]
+fn test_day2() {
+    agree("day2", day2::run, &[(1, 12), (2, 2)], &[]);
+    agree("day2", day2::run, &[(1, 89), (2, 76)], &[]);
+}
+
+#[test]
+fn test_day5() {
+    agree("day5", day5::run, &[], &[1]);
+    agree("day5", day5::run, &[], &[5]);
+}
+
+#[test]
+fn test_day7() {
+    agree("day7", day7::run, &[], &[4, 0]);
+    agree("day7", day7::run, &[], &[9, 0, 5]);
+}
+
+#[test]
+fn test_day9() {
+    agree("day9", day9::run, &[], &[1]);
+    agree("day9", day9::run, &[], &[2]);
+}
+
+#[test]
+fn test_day11() {
+    let colors: Vec<Int> = (0..200).map(|i| i % 2).collect();
+    agree("day11", day11::run, &[], &colors);
+}
+
+#[test]
+fn test_day13() {
+    agree("day13", day13::run, &[], &[]);
+    // play for free with the joystick held still
+    agree("day13", day13::run, &[(0, 2)], &[0; 100]);
+}
+
+#[test]
+fn test_day15() {
+    let moves: Vec<Int> = (0..200).map(|i| i % 4 + 1).collect();
+    agree("day15", day15::run, &[], &moves);
+}
+
+#[test]
+fn test_day17() {
+    agree("day17", day17::run, &[], &[]);
+    let routine: Vec<Int> = "A\nL,4\nL,4\nL,4\nn\n".bytes().map(Int::from).collect();
+    agree("day17", day17::run, &[(0, 2)], &routine);
+}
+
+#[test]
+fn test_day19() {
+    for (x, y) in [(0, 0), (10, 20), (30, 30)].iter() {
+        agree("day19", day19::run, &[], &[*x, *y]);
+    }
+}
@@ -0,0 +1,311 @@
+//! Assembler for a text syntax using the same mnemonics and operand notation
+//! as the disassembler:
+//!
+//! ```text
+//! ; count down from 3, printing each number
+//!         ADD #3, #0, [count]
+//! loop:   OUT [count]
+//!         ADD [count], #-1, [count]
+//!         JNZ [count], #loop
+//!         END
+//! count:  DATA 0
+//! ```
+//!
+//! Operands are `[x]` (or just `x`) for position mode, `#x` for immediate
+//! mode and `rb+x` for relative mode, where x is a number, a label or a label
+//! plus or minus a number. Labels are defined by `name:` at the start of a
+//! line and evaluate to the address of what follows. `DATA` emits its
+//! comma-separated values as-is, and `;` starts a comment. A numeric label
+//! such as `0012:`, as printed by the disassembler, asserts the current
+//! address.
+
+use super::{Int, Mode, Operation};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+/// An error in the assembly source, with the line (starting at 1) it was found
+/// on.
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+/// A value that may refer to a label, resolved once all labels are known.
+enum Expr {
+    Number(Int),
+    Label(String, Int),
+}
+
+struct Operand {
+    mode: Mode,
+    expr: Expr,
+}
+
+enum Statement {
+    Instruction(Operation, Vec<Operand>),
+    Data(Vec<Expr>),
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_expr(s: &str) -> Result<Expr, String> {
+    let s = s.trim();
+    if let Ok(n) = Int::from_str(s) {
+        return Ok(Expr::Number(n));
+    }
+    let (name, offset) = match s.find(['+', '-']) {
+        Some(i) => {
+            let offset = Int::from_str(s[i..].replace(' ', "").trim_start_matches('+'))
+                .map_err(|_| format!["invalid offset in \"{}\"", s])?;
+            (s[..i].trim(), offset)
+        }
+        None => (s, 0),
+    };
+    if !is_identifier(name) || name == "rb" {
+        return Err(format!["invalid value \"{}\"", s]);
+    }
+    Ok(Expr::Label(name.to_string(), offset))
+}
+
+fn parse_operand(s: &str) -> Result<Operand, String> {
+    let s = s.trim();
+    if let Some(rest) = s.strip_prefix('#') {
+        return Ok(Operand {
+            mode: Mode::Value,
+            expr: parse_expr(rest)?,
+        });
+    }
+    if let Some(rest) = s.strip_prefix('[') {
+        let inner = rest
+            .strip_suffix(']')
+            .ok_or(format!["missing ] in \"{}\"", s])?;
+        return Ok(Operand {
+            mode: Mode::Pointer,
+            expr: parse_expr(inner)?,
+        });
+    }
+    if let Some(rest) = s.strip_prefix("rb") {
+        let rest = rest.trim();
+        let expr = if rest.is_empty() {
+            Expr::Number(0)
+        } else if let Some(r) = rest.strip_prefix('+') {
+            parse_expr(r)?
+        } else if let Some(r) = rest.strip_prefix('-') {
+            match parse_expr(r)? {
+                Expr::Number(n) => Expr::Number(-n),
+                Expr::Label(..) => return Err(format!["can't subtract a label in \"{}\"", s]),
+            }
+        } else {
+            return Err(format!["invalid relative operand \"{}\"", s]);
+        };
+        return Ok(Operand {
+            mode: Mode::Relative,
+            expr,
+        });
+    }
+    Ok(Operand {
+        mode: Mode::Pointer,
+        expr: parse_expr(s)?,
+    })
+}
+
+/// Parse one line, without labels or comments, into a statement.
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim()),
+        None => (text, ""),
+    };
+    let args: Vec<&str> = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').collect()
+    };
+    if mnemonic.eq_ignore_ascii_case("DATA") {
+        if args.is_empty() {
+            return Err("DATA needs at least one value".to_string());
+        }
+        return Ok(Statement::Data(
+            args.iter()
+                .map(|a| parse_expr(a))
+                .collect::<Result<_, _>>()?,
+        ));
+    }
+    let op =
+        Operation::from_mnemonic(mnemonic).ok_or(format!["unknown mnemonic \"{}\"", mnemonic])?;
+    if args.len() != op.len() - 1 {
+        return Err(format![
+            "{} takes {} operands, found {}",
+            mnemonic,
+            op.len() - 1,
+            args.len()
+        ]);
+    }
+    let operands: Vec<Operand> = args
+        .iter()
+        .map(|a| parse_operand(a))
+        .collect::<Result<_, _>>()?;
+    if let Some(n) = op.writes() {
+        if operands[n - 1].mode == Mode::Value {
+            return Err(format!["{} can't write to an immediate operand", mnemonic]);
+        }
+    }
+    Ok(Statement::Instruction(op, operands))
+}
+
+impl Statement {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instruction(op, _) => op.len(),
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+/// Assemble source into Intcode, ready to be loaded with
+/// `Program::from_code`.
+pub fn assemble(source: &str) -> Result<Vec<Int>, AsmError> {
+    let mut labels: HashMap<String, Int> = HashMap::new();
+    let mut statements: Vec<(usize, Statement)> = Vec::new();
+    let mut addr: Int = 0;
+
+    // first pass: parse statements and find label addresses
+    for (i, raw) in source.lines().enumerate() {
+        let line = i + 1;
+        let err = |message| AsmError { line, message };
+        let mut text = raw.split(';').next().unwrap().trim();
+        while let Some(pos) = text.find(':') {
+            let name = text[..pos].trim();
+            if let Ok(n) = Int::from_str(name) {
+                if n != addr {
+                    return Err(err(format!["address {} should be {}", n, addr]));
+                }
+            } else if is_identifier(name) && name != "rb" {
+                if labels.insert(name.to_string(), addr).is_some() {
+                    return Err(err(format!["duplicate label \"{}\"", name]));
+                }
+            } else {
+                return Err(err(format!["invalid label \"{}\"", name]));
+            }
+            text = text[pos + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(text).map_err(err)?;
+        addr += statement.len() as Int;
+        statements.push((line, statement));
+    }
+
+    // second pass: emit code with labels resolved
+    let mut code = Vec::with_capacity(addr as usize);
+    for (line, statement) in statements {
+        let resolve = |expr: &Expr| match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Label(name, offset) => labels.get(name).map(|a| a + offset).ok_or(AsmError {
+                line,
+                message: format!["undefined label \"{}\"", name],
+            }),
+        };
+        match statement {
+            Statement::Instruction(op, operands) => {
+                let mut instruction = op.code();
+                let mut place = 100;
+                for operand in operands.iter() {
+                    instruction += place
+                        * match operand.mode {
+                            Mode::Pointer => 0,
+                            Mode::Value => 1,
+                            Mode::Relative => 2,
+                        };
+                    place *= 10;
+                }
+                code.push(instruction);
+                for operand in operands.iter() {
+                    code.push(resolve(&operand.expr)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in values.iter() {
+                    code.push(resolve(value)?);
+                }
+            }
+        }
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod test_asm {
+    use super::*;
+
+    #[test]
+    fn test_instructions() {
+        let source = "
+            ADD [100], #5, rb+3
+            REL #-2   ; comment
+            OUT rb-1
+            INP 7
+            END
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![21001, 100, 5, 3, 109, -2, 204, -1, 3, 7, 99]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let source = "
+            start:  JZ #0, #end
+            value:  DATA 1, 2, value+1
+            end:    OUT [value + 1]
+                    JNZ #1, #start
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![1106, 0, 6, 1, 2, 4, 4, 4, 1105, 1, 0]
+        );
+    }
+
+    #[test]
+    fn test_address_labels() {
+        assert_eq!(
+            assemble("0000: OUT #1\n0002: END").unwrap(),
+            vec![104, 1, 99]
+        );
+        assert_eq!(assemble("0000: OUT #1\n0003: END").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |s| assemble(s).unwrap_err();
+        assert_eq!(err("FOO 1").message, "unknown mnemonic \"FOO\"");
+        assert_eq!(err("ADD 1, 2").message, "ADD takes 3 operands, found 2");
+        assert_eq!(
+            err("INP #1").message,
+            "INP can't write to an immediate operand"
+        );
+        assert_eq!(err("\nOUT [nowhere]").line, 2);
+        assert_eq!(err("a: END\na: END").message, "duplicate label \"a\"");
+        assert_eq!(err("DATA").message, "DATA needs at least one value");
+    }
+}
@@ -1,5 +1,10 @@
 extern crate intcode;
 use argh::FromArgs;
+use intcode::ports::{AsciiInput, AsciiOutput};
+use intcode::trace::{JsonTracer, TextTracer};
+use intcode::{Access, Input, IntInput, IntOutput, Options, Output, RunState, Tracer};
+use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::io;
 
@@ -15,6 +20,10 @@ struct Args {
 enum Subcommand {
     Run(CommandRun),
     Debug(CommandDebug),
+    Disasm(CommandDisasm),
+    Asm(CommandAsm),
+    Compile(CommandCompile),
+    GdbStub(CommandGdbStub),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -25,11 +34,30 @@ struct CommandRun {
     /// source code file
     filename: String,
     #[argh(switch, short = 't')]
-    /// trace program execution
+    /// trace program execution to stderr
     trace: bool,
+    #[argh(option)]
+    /// write a trace of program execution to file as JSON lines
+    trace_file: Option<String>,
     #[argh(switch, short = 'p')]
     /// print final memory status
     print: bool,
+    #[argh(switch, short = 'a')]
+    /// read and write text as ASCII character codes
+    ascii: bool,
+    #[argh(option, short = 'w')]
+    /// report writes to ADDR or START-END (may be repeated)
+    watch: Vec<String>,
+    #[argh(switch, short = 'l')]
+    /// fail when the program is stuck in an infinite loop
+    detect_loops: bool,
+    #[argh(switch)]
+    /// print an execution profile to stderr
+    profile: bool,
+    #[argh(option)]
+    /// write the execution profile to file, as CSV if it ends in .csv or
+    /// JSON otherwise
+    profile_out: Option<String>,
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -39,21 +67,131 @@ struct CommandDebug {
     #[argh(positional)]
     /// source code file
     filename: String,
+    #[argh(option, short = 'i')]
+    /// file of input to queue: numbers, or lines of text starting with "
+    input: Option<String>,
+    #[argh(option)]
+    /// run debugger commands from file (- for standard input) without the
+    /// terminal UI
+    script: Option<String>,
 }
 
-fn main() -> io::Result<()> {
+#[derive(FromArgs, PartialEq, Debug)]
+/// print program disassembly
+#[argh(subcommand, name = "disasm")]
+struct CommandDisasm {
+    #[argh(positional)]
+    /// source code file
+    filename: String,
+    #[argh(option, short = 's', default = "0")]
+    /// first address to print
+    start: intcode::Int,
+    #[argh(option, short = 'e', default = "intcode::Int::MAX")]
+    /// address to stop printing at
+    end: intcode::Int,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// assemble program source to intcode
+#[argh(subcommand, name = "asm")]
+struct CommandAsm {
+    #[argh(positional)]
+    /// assembly source file
+    filename: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// translate program to a Rust module
+#[argh(subcommand, name = "compile")]
+struct CommandCompile {
+    #[argh(positional)]
+    /// source code file
+    filename: String,
+    #[argh(option, short = 'n', default = "String::from(\"run\")")]
+    /// name of the generated function
+    name: String,
+    #[argh(option, short = 'o')]
+    /// file to write the module to instead of standard output
+    output: Option<String>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// debug program with a GDB remote protocol client
+#[argh(subcommand, name = "gdbstub")]
+struct CommandGdbStub {
+    #[argh(positional)]
+    /// source code file
+    filename: String,
+    #[argh(option, short = 'l', default = "String::from(\"127.0.0.1:1234\")")]
+    /// address to listen on
+    listen: String,
+    #[argh(option, short = 'i')]
+    /// file of input to queue: numbers, or lines of text starting with "
+    input: Option<String>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
     let args: Args = argh::from_env();
     match args.subcommand {
         Subcommand::Run(r) => {
             let f = File::open(r.filename)?;
             let reader = io::BufReader::new(f);
             let mut prog = intcode::Program::new(reader);
-            prog.exe(
-                0,
-                r.trace,
-                intcode::Input::Reader(&mut io::stdin().lock()),
-                intcode::Output::Writer(&mut io::stdout().lock()),
-            )?;
+            for w in r.watch.iter() {
+                let (start, end) = intcode::watch::parse_range(w)
+                    .ok_or(format!["invalid watch address \"{}\"", w])?;
+                prog.watch(start, end, Access::Write);
+            }
+            prog.set_options(Options {
+                profile: r.profile || r.profile_out.is_some(),
+                detect_loops: r.detect_loops,
+                ..prog.options().clone()
+            });
+            let mut stdin = io::stdin().lock();
+            let mut stdout = io::stdout().lock();
+            let (mut input, mut output): (Box<dyn IntInput>, Box<dyn IntOutput>) = if r.ascii {
+                (
+                    Box::new(AsciiInput::new(&mut stdin)),
+                    Box::new(AsciiOutput::new(&mut stdout)),
+                )
+            } else {
+                (
+                    Box::new(Input::Reader(&mut stdin)),
+                    Box::new(Output::Writer(&mut stdout)),
+                )
+            };
+            let mut tracer: Option<Box<dyn Tracer>> = match (&r.trace_file, r.trace) {
+                (Some(_), true) => return Err("-t and --trace-file can't be used together".into()),
+                (Some(filename), false) => Some(Box::new(JsonTracer::new(io::BufWriter::new(
+                    File::create(filename)?,
+                )))),
+                (None, true) => Some(Box::new(TextTracer::stderr())),
+                (None, false) => None,
+            };
+            let result = loop {
+                match prog.exe(tracer.as_deref_mut(), &mut input, &mut output) {
+                    Ok(RunState::Watchpoint(_)) => {
+                        for hit in prog.watch_hits() {
+                            eprintln!["watch {}", hit];
+                        }
+                    }
+                    result => break result,
+                }
+            };
+            // report the profile even if the program failed
+            if let Some(profile) = prog.profile() {
+                if r.profile {
+                    eprint!["{}", profile];
+                }
+                if let Some(filename) = &r.profile_out {
+                    if filename.ends_with(".csv") {
+                        fs::write(filename, profile.to_csv())?;
+                    } else {
+                        fs::write(filename, profile.to_json())?;
+                    }
+                }
+            }
+            result?;
             if r.print {
                 println!["{}", prog];
             }
@@ -62,7 +200,53 @@ fn main() -> io::Result<()> {
             let f = File::open(r.filename)?;
             let reader = io::BufReader::new(f);
             let prog = intcode::Program::new(reader);
-            intcode::debugger::debug(prog)?;
+            let input = match r.input {
+                Some(filename) => intcode::debugger::parse_script(&fs::read_to_string(filename)?)?,
+                None => Vec::new(),
+            };
+            let stdout = io::stdout();
+            match r.script.as_deref() {
+                Some("-") => {
+                    let stdin = io::stdin();
+                    intcode::debugger::run_script(prog, &input, stdin.lock(), stdout.lock(), false)?
+                }
+                Some(filename) => {
+                    let script = io::BufReader::new(File::open(filename)?);
+                    intcode::debugger::run_script(prog, &input, script, stdout.lock(), true)?
+                }
+                None => intcode::debugger::debug(prog, &input)?,
+            }
+        }
+        Subcommand::Disasm(r) => {
+            let f = File::open(r.filename)?;
+            let reader = io::BufReader::new(f);
+            let prog = intcode::Program::new(reader);
+            for line in intcode::disassemble::disassemble(&prog, r.start, r.end) {
+                println!["{}", line];
+            }
+        }
+        Subcommand::Asm(r) => {
+            let source = fs::read_to_string(r.filename)?;
+            let code = intcode::asm::assemble(&source)?;
+            let code: Vec<String> = code.iter().map(|i| i.to_string()).collect();
+            println!["{}", code.join(",")];
+        }
+        Subcommand::Compile(r) => {
+            let prog = intcode::Program::new(io::BufReader::new(File::open(r.filename)?));
+            let source = intcode::compile::compile(&prog, &r.name);
+            match r.output {
+                Some(filename) => fs::write(filename, source)?,
+                None => print!["{}", source],
+            }
+        }
+        Subcommand::GdbStub(r) => {
+            let prog = intcode::Program::new(io::BufReader::new(File::open(r.filename)?));
+            let input = match r.input {
+                Some(filename) => intcode::debugger::parse_script(&fs::read_to_string(filename)?)?,
+                None => Vec::new(),
+            };
+            eprintln!["waiting for a client on {}", r.listen];
+            intcode::gdbstub::listen(prog, &input, &r.listen)?;
         }
     };
     Ok(())
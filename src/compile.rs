@@ -0,0 +1,417 @@
+//! Ahead-of-time translation of Intcode programs to Rust source.
+//!
+//! `compile` turns a program image into a Rust module holding one function
+//! that runs the program like `Program::exe`. The image is split into
+//! straight-line blocks of arithmetic, comparison, relative base and jump
+//! instructions, each compiled to an arm of a `match` on the instruction
+//! pointer. Input, output and END instructions, and addresses that don't
+//! start a block, such as the targets of computed jumps, are executed by the
+//! interpreter. A block whose words are overwritten, before or during the
+//! run, is never entered again and its instructions are interpreted instead,
+//! so self-modifying programs behave as they do in the interpreter.
+//!
+//! The remaining functions in this module support the generated code only,
+//! and are hidden from the documentation as they aren't a stable API.
+
+use super::disassemble::{disassemble, Line};
+use super::{ArithOp, Int, IntInput, IntOutput, IntcodeError, Mode, Operation, Program};
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+
+/// number of array elements per line of generated source
+const PER_LINE: usize = 16;
+
+/// Whether the interpreter is needed to execute op.
+fn interpreted(op: Operation) -> bool {
+    matches!(op, Operation::Input | Operation::Output | Operation::End)
+}
+
+fn is_jump(op: Operation) -> bool {
+    op == Operation::JumpNotZero || op == Operation::JumpZero
+}
+
+/// Expression for the value of a parameter of the instruction at addr.
+fn read(addr: Int, mode: Mode, raw: Int) -> String {
+    match mode {
+        Mode::Pointer => format!["prog.peek({})?", raw],
+        Mode::Value => raw.to_string(),
+        Mode::Relative => format!["prog.peek({})?", relative(addr, raw)],
+    }
+}
+
+/// Expression for an address relative to the relative base, for the
+/// instruction at addr.
+fn relative(addr: Int, raw: Int) -> String {
+    match raw {
+        0 => "*rb".to_string(),
+        r => format!["offset(prog, {}, {}, *rb)?", addr, r],
+    }
+}
+
+/// Rust source for the straight-line instructions of a block, ending with
+/// the assignment of the next instruction pointer. blocks holds the block
+/// number plus one of each word of compiled code.
+fn block_source(lines: &[&Line], blocks: &[u32]) -> String {
+    let mut s = String::new();
+    for (n, line) in lines.iter().enumerate() {
+        let (op, modes) = line.instruction().unwrap();
+        let addr = line.address();
+        let w = line.words();
+        let next = addr + w.len() as Int;
+        s += &format!["                // {}\n", line];
+        if n > 0 {
+            s += &format!["                *ip = {};\n", addr];
+        }
+        let value = match op {
+            Operation::Add | Operation::Mul => format![
+                "arith(prog, {}, {}, ArithOp::{}, {})?",
+                addr,
+                read(addr, modes[0], w[1]),
+                if op == Operation::Add { "Add" } else { "Mul" },
+                read(addr, modes[1], w[2])
+            ],
+            Operation::LessThan => format![
+                "({} < {}) as Int",
+                read(addr, modes[0], w[1]),
+                read(addr, modes[1], w[2])
+            ],
+            Operation::EqualTo => format![
+                "({} == {}) as Int",
+                read(addr, modes[0], w[1]),
+                read(addr, modes[1], w[2])
+            ],
+            Operation::RelBase => {
+                s += &format![
+                    "                *rb = arith(prog, {}, *rb, ArithOp::Add, {})?;\n",
+                    addr,
+                    read(addr, modes[0], w[1])
+                ];
+                continue;
+            }
+            Operation::JumpNotZero | Operation::JumpZero => {
+                let test = if op == Operation::JumpNotZero {
+                    "!="
+                } else {
+                    "=="
+                };
+                let target = read(addr, modes[1], w[2]);
+                s += &match modes[0] {
+                    Mode::Value if (w[1] != 0) == (op == Operation::JumpNotZero) => {
+                        format!["                *ip = {};\n", target]
+                    }
+                    Mode::Value => format!["                *ip = {};\n", next],
+                    m => format![
+                        "                *ip = if {} {} 0 {{ {} }} else {{ {} }};\n",
+                        read(addr, m, w[1]),
+                        test,
+                        target,
+                        next
+                    ],
+                };
+                return s;
+            }
+            _ => unreachable!["{} is interpreted", op],
+        };
+        let last = n == lines.len() - 1;
+        match modes[2] {
+            Mode::Relative => {
+                s += &format!["                let d = {};\n", relative(addr, w[3])];
+                s += &format!["                prog.poke(d, {})?;\n", value];
+                if last {
+                    s += "                mark(&mut dirty, &BLOCKS, d);\n";
+                } else {
+                    s += &format![
+                        "                if mark(&mut dirty, &BLOCKS, d) {{\n                    \
+                         *ip = {};\n                    continue;\n                }}\n",
+                        next
+                    ];
+                }
+            }
+            _ => {
+                s += &format!["                prog.poke({}, {})?;\n", w[3], value];
+                if let Some(b) = block_of(blocks, w[3]) {
+                    // blocks end at writes to compiled code
+                    s += &format!["                dirty[{}] = true;\n", b];
+                }
+            }
+        }
+    }
+    let last = lines.last().unwrap();
+    s += &format![
+        "                *ip = {};\n",
+        last.address() + last.words().len() as Int
+    ];
+    s
+}
+
+/// Index of the compiled block containing addr, if any.
+fn block_of(blocks: &[u32], addr: Int) -> Option<usize> {
+    let i: usize = addr.try_into().ok()?;
+    match blocks.get(i) {
+        Some(&b) if b > 0 => Some(b as usize - 1),
+        _ => None,
+    }
+}
+
+/// Format values as the elements of a constant array.
+fn array<T: ToString>(values: &[T]) -> String {
+    values
+        .chunks(PER_LINE)
+        .map(|c| {
+            let c: Vec<String> = c.iter().map(|v| v.to_string()).collect();
+            format!["    {},\n", c.join(", ")]
+        })
+        .collect()
+}
+
+/// Translate the program image of prog to the source of a Rust module
+/// defining `pub fn name(prog: &mut Program, input: &mut I, output: &mut O)
+/// -> Result<(), IntcodeError>`, which runs prog from its instruction pointer
+/// until it halts. The compiled function must only be used with programs
+/// loaded from the same code, which may have been modified since. The source
+/// has no inner attributes, so it can be a module file or be pulled into one
+/// with `include!`.
+///
+/// Compiled instructions aren't traced, profiled, recorded in the history,
+/// checked against watchpoints or counted in `Program::steps`.
+pub fn compile(prog: &Program, name: &str) -> String {
+    let lines = disassemble(prog, 0, Int::MAX);
+    let compiled = |l: &Line| l.instruction().is_some_and(|(op, _)| !interpreted(op));
+    let code: BTreeSet<Int> = lines
+        .iter()
+        .filter(|l| compiled(l))
+        .flat_map(|l| l.address()..l.address() + l.words().len() as Int)
+        .collect();
+    // jump targets known in advance must start a block
+    let targets: BTreeSet<Int> = lines
+        .iter()
+        .filter_map(|l| match l.instruction() {
+            Some((op, modes)) if is_jump(op) && modes[1] == Mode::Value => Some(l.words()[2]),
+            _ => None,
+        })
+        .collect();
+    let mut blocks: Vec<Vec<&Line>> = Vec::new();
+    let mut open = false;
+    for line in lines.iter() {
+        let (op, modes) = match line.instruction() {
+            Some((op, modes)) if !interpreted(op) => (op, modes),
+            _ => {
+                open = false;
+                continue;
+            }
+        };
+        if !open || targets.contains(&line.address()) {
+            blocks.push(Vec::new());
+        }
+        blocks.last_mut().unwrap().push(line);
+        let writes_code = op
+            .writes()
+            .is_some_and(|n| modes[n - 1] == Mode::Pointer && code.contains(&line.words()[n]));
+        open = !is_jump(op) && !writes_code;
+    }
+
+    let len = code.iter().next_back().map_or(0, |a| a + 1) as usize;
+    let mut image = vec![0; len];
+    let mut block_words = vec![0u32; len];
+    for (b, lines) in blocks.iter().enumerate() {
+        for line in lines {
+            for (i, w) in line.words().iter().enumerate() {
+                let a = line.address() as usize + i;
+                image[a] = *w;
+                block_words[a] = b as u32 + 1;
+            }
+        }
+    }
+
+    let mut body = String::new();
+    for (b, lines) in blocks.iter().enumerate() {
+        body += &format![
+            "            {} if !dirty[{}] => {{\n",
+            lines[0].address(),
+            b
+        ];
+        body += &block_source(lines, &block_words);
+        body += "            }\n";
+    }
+    // the support functions used by the blocks, besides those always used
+    let mut imports = vec!["check", "interpret"];
+    for f in ["arith", "mark", "offset"].iter() {
+        if body.contains(&format!["{}(", f]) {
+            imports.push(f);
+        }
+    }
+    imports.sort_unstable();
+    let mut types = vec!["Int", "IntInput", "IntOutput", "IntcodeError", "Program"];
+    if body.contains("ArithOp::") {
+        types.insert(0, "ArithOp");
+    }
+    format![
+        "// Generated by `intcode compile` from a {}-word program. Do not edit.\n\
+         \n\
+         use intcode::compile::{{{}}};\n\
+         use intcode::{{{}}};\n\
+         \n\
+         /// words of the compiled instructions\n\
+         const IMAGE: [Int; {}] = [\n{}];\n\
+         \n\
+         /// block number plus one of each word of compiled code, or 0\n\
+         const BLOCKS: [u32; {}] = [\n{}];\n\
+         \n\
+         /// Run prog from its instruction pointer until it halts.\n\
+         pub fn {}<I: IntInput + ?Sized, O: IntOutput + ?Sized>(\n    \
+             prog: &mut Program,\n    \
+             input: &mut I,\n    \
+             output: &mut O,\n\
+         ) -> Result<(), IntcodeError> {{\n    \
+             if prog.is_halted() {{\n        \
+                 return Ok(());\n    \
+             }}\n    \
+             let mut ip = prog.ip();\n    \
+             let mut rb = prog.rel_base();\n    \
+             let result = exec(prog, &mut ip, &mut rb, input, output);\n    \
+             if result.is_err() {{\n        \
+                 prog.set_ip(ip);\n        \
+                 prog.set_rel_base(rb);\n    \
+             }}\n    \
+             result\n\
+         }}\n\
+         \n\
+         fn exec<I: IntInput + ?Sized, O: IntOutput + ?Sized>(\n    \
+             prog: &mut Program,\n    \
+             ip: &mut Int,\n    \
+             rb: &mut Int,\n    \
+             input: &mut I,\n    \
+             output: &mut O,\n\
+         ) -> Result<(), IntcodeError> {{\n    \
+             let mut dirty = check(prog, &IMAGE, &BLOCKS);\n    \
+             loop {{\n        \
+                 match *ip {{\n\
+         {}            \
+                 _ => {{\n                \
+                     if interpret(prog, ip, rb, &mut dirty, &BLOCKS, input, output)? {{\n                    \
+                         return Ok(());\n                \
+                     }}\n            \
+                 }}\n        \
+             }}\n    \
+         }}\n\
+         }}\n",
+        prog.mem.dense().len(),
+        imports.join(", "),
+        types.join(", "),
+        len,
+        array(&image),
+        len,
+        array(&block_words),
+        name,
+        body
+    ]
+}
+
+/// Find the compiled blocks whose words in image no longer match the memory
+/// of prog, returning a flag for each block. blocks holds the block number
+/// plus one of each word of image, or 0 for words not compiled.
+#[doc(hidden)]
+pub fn check(prog: &Program, image: &[Int], blocks: &[u32]) -> Vec<bool> {
+    let mut dirty = vec![false; blocks.iter().max().copied().unwrap_or(0) as usize];
+    for (a, (w, b)) in image.iter().zip(blocks).enumerate() {
+        if *b > 0 && prog.peek(a as Int).ok() != Some(*w) {
+            dirty[*b as usize - 1] = true;
+        }
+    }
+    dirty
+}
+
+/// Flag the compiled block containing addr, if any, as modified, and return
+/// whether there was one.
+#[doc(hidden)]
+pub fn mark(dirty: &mut [bool], blocks: &[u32], addr: Int) -> bool {
+    match block_of(blocks, addr) {
+        Some(b) => {
+            dirty[b] = true;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Compute lhs op rhs for the instruction at address, according to the
+/// `Options::overflow` of prog.
+#[doc(hidden)]
+pub fn arith(
+    prog: &Program,
+    address: Int,
+    lhs: Int,
+    op: ArithOp,
+    rhs: Int,
+) -> Result<Int, IntcodeError> {
+    prog.arith(address, lhs, op, rhs)
+}
+
+/// Compute the address base + n for the instruction at address, which is an
+/// error on overflow whatever the `Options::overflow` of prog.
+#[doc(hidden)]
+pub fn offset(prog: &Program, address: Int, base: Int, n: Int) -> Result<Int, IntcodeError> {
+    prog.offset(address, base, n)
+}
+
+/// Execute the instruction at ip with the interpreter, updating ip and the
+/// relative base rb, and flag any compiled block it writes to. Returns
+/// whether the program halted.
+#[doc(hidden)]
+pub fn interpret<I: IntInput + ?Sized, O: IntOutput + ?Sized>(
+    prog: &mut Program,
+    ip: &mut Int,
+    rb: &mut Int,
+    dirty: &mut [bool],
+    blocks: &[u32],
+    input: &mut I,
+    output: &mut O,
+) -> Result<bool, IntcodeError> {
+    prog.set_ip(*ip);
+    prog.set_rel_base(*rb);
+    prog.step(None, input, output)?;
+    if let Some(addr) = prog.last_write() {
+        mark(dirty, blocks, addr);
+    }
+    *ip = prog.ip();
+    *rb = prog.rel_base();
+    Ok(prog.is_halted())
+}
+
+#[cfg(test)]
+mod test_compile {
+    use super::*;
+
+    #[test]
+    fn test_blocks() {
+        // count down from 3: a block at 4 looping on itself, after an ADD
+        // that writes to the JNZ
+        let prog = Program::from_code(vec![1101, 3, 0, 20, 1001, 20, -1, 20, 1005, 20, 4, 99]);
+        let s = compile(&prog, "countdown");
+        assert!(s.contains("pub fn countdown<"));
+        assert!(s.contains("const BLOCKS: [u32; 11] = [\n    1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2,\n];"));
+        assert!(s.contains(
+            "            4 if !dirty[1] => {\n                \
+             // 0004: ADD [20], #-1, [20]\n                \
+             prog.poke(20, arith(prog, 4, prog.peek(20)?, ArithOp::Add, -1)?)?;\n                \
+             // 0008: JNZ [20], #4\n                \
+             *ip = 8;\n                \
+             *ip = if prog.peek(20)? != 0 { 4 } else { 11 };\n            \
+             }\n"
+        ));
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // the first ADD writes into the second, which ends its block
+        let prog = Program::from_code(vec![1101, 1, 1, 6, 21101, 0, 0, 0, 99]);
+        let s = compile(&prog, "run");
+        assert!(s.contains(
+            "prog.poke(6, arith(prog, 0, 1, ArithOp::Add, 1)?)?;\n                dirty[1] = true;\n"
+        ));
+        assert!(s.contains(
+            "let d = *rb;\n                prog.poke(d, arith(prog, 4, 0, ArithOp::Add, 0)?)?;\n                \
+             mark(&mut dirty, &BLOCKS, d);\n"
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+//! Debugger driven by console commands read from a script or standard input,
+//! with plain text output so sessions can be recorded and replayed.
+
+use super::{join, Debugger, HELP};
+use crate::{Int, Program};
+use std::io;
+use std::io::prelude::*;
+
+/// Run the console commands read from commands against prog, with the values
+/// in input queued for its input instructions, and write each command's
+/// result and any new program output to out. With echo, each command is
+/// written after a `> ` prompt so out reads as a transcript; otherwise only
+/// the prompt is written, for a user typing at a terminal.
+///
+/// Besides the console commands, `info` describes the current instruction,
+/// `io` shows the captured input and output and `quit` stops reading.
+pub fn run_script<R: BufRead, W: Write>(
+    prog: Program,
+    input: &[Int],
+    mut commands: R,
+    mut out: W,
+    echo: bool,
+) -> io::Result<()> {
+    let mut d = Debugger::new(prog, input);
+    let mut line = String::new();
+    loop {
+        write!(out, "> ")?;
+        if !echo {
+            out.flush()?;
+        }
+        line.clear();
+        if commands.read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            return Ok(());
+        }
+        let command = line.trim();
+        if echo {
+            writeln!(out, "{}", command)?;
+        }
+        let outputs = d.outputs.len();
+        match command {
+            "q" | "quit" => return Ok(()),
+            "info" => writeln!(out, "{}", d.instruction_string())?,
+            "io" => writeln!(out, "{}", d.io_string())?,
+            "h" | "help" => writeln!(out, "{}, info, io", HELP)?,
+            _ => {
+                d.status.clear();
+                d.command(command);
+                if !d.status.is_empty() {
+                    writeln!(out, "{}", d.status)?;
+                }
+            }
+        }
+        if d.outputs.len() > outputs {
+            writeln!(out, "output: {}", join(d.outputs[outputs..].iter()))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_headless {
+    use super::*;
+
+    fn transcript(code: &str, commands: &str) -> String {
+        let prog = Program::new(io::Cursor::new(code));
+        let mut out = Vec::new();
+        run_script(prog, &[5], io::Cursor::new(commands), &mut out, true).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_script() {
+        let code = "3,11,1001,11,1,11,4,11,104,-1,99,0";
+        assert_eq!(
+            transcript(
+                code,
+                "break 6\ncontinue\nprint 11\nstep 2\ninput 3\ncontinue\nio\n"
+            ),
+            "> break 6\n\
+             breakpoint set at 6\n\
+             > continue\n\
+             breakpoint at 6\n\
+             > print 11\n\
+             [11] = 6\n\
+             > step 2\n\
+             output: 6, -1\n\
+             > input 3\n\
+             1 values queued\n\
+             > continue\n\
+             halted\n\
+             > io\n\
+             queued: 3\n\
+             inputs: 5\n\
+             output:\n\
+             6, -1\n\
+             > \n"
+        );
+    }
+
+    #[test]
+    fn test_quit() {
+        assert_eq!(
+            transcript("99", "info\nquit\nstep\n"),
+            "> info\n\
+             ip:       0\n\
+             rel_base: 0\n\
+             instr:    99 END\n\
+             halts\n\
+             > quit\n"
+        );
+    }
+}
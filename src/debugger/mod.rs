@@ -0,0 +1,949 @@
+//! Debugger for Intcode programs, driven either by an interactive terminal
+//! UI or by a script of console commands.
+
+use super::ports::{AsciiOutput, PortError};
+use super::watch::parse_range;
+use super::{
+    Access, ArithOp, Instruction, Int, IntOutput, IntcodeError, Mode, Operation, Options, Program,
+};
+use cursive::theme::{BaseColor, Color, ColorStyle, Effect, Style};
+use cursive::utils::markup::StyledString;
+use std::collections::{BTreeSet, VecDeque};
+use std::str::FromStr;
+
+mod headless;
+mod tui;
+
+pub use headless::run_script;
+pub use tui::debug;
+
+/// number of executed instructions kept for stepping back
+const HISTORY: usize = 10000;
+/// number of memory cells on each row of the Code panel
+const COLUMNS: Int = 10;
+/// number of rows shown in the Code panel
+const ROWS: Int = 28;
+/// number of cells either side of rel_base shown in the Instruction panel
+const REL_RADIUS: Int = 4;
+/// largest number of cells the print command shows
+const MAX_PRINT: Int = 1000;
+/// last row that can be at the top of the Code panel without the panel
+/// running past the end of Int
+const MAX_TOP: Int = Int::MAX / COLUMNS - ROWS;
+
+struct Debugger {
+    program: Program,
+    breakpoints: BTreeSet<Int>,
+    status: String,
+    /// address of the selected memory cell
+    cursor: Int,
+    /// first row shown in the Code panel
+    top: Int,
+    /// show memory in hexadecimal rather than decimal
+    hex: bool,
+    /// input consumed before prompting the user
+    queue: VecDeque<Int>,
+    /// every input the program has read
+    inputs: Vec<Int>,
+    /// every value the program has written
+    outputs: Vec<Int>,
+    /// show output as text rather than numbers
+    ascii: bool,
+    /// commands entered in the console
+    history: Vec<String>,
+    /// position in history while recalling commands
+    history_pos: usize,
+}
+
+impl Debugger {
+    /// Debug program, with the values in input queued for its input
+    /// instructions.
+    fn new(mut program: Program, input: &[Int]) -> Debugger {
+        program.set_options(Options {
+            history: HISTORY,
+            ..program.options().clone()
+        });
+        Debugger {
+            program,
+            breakpoints: BTreeSet::new(),
+            status: String::new(),
+            cursor: 0,
+            top: 0,
+            hex: false,
+            queue: input.iter().copied().collect(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            ascii: false,
+            history: Vec::new(),
+            history_pos: 0,
+        }
+    }
+
+    pub fn cur_val(&self) -> Int {
+        self.program.peek(self.program.ip()).unwrap_or_default()
+    }
+
+    fn cur_len(&self) -> usize {
+        self.cur_val().op().map_or(1, |op| op.len())
+    }
+
+    fn format_value(&self, v: Int) -> String {
+        match (self.hex, v < 0) {
+            (false, _) => v.to_string(),
+            (true, false) => format!["{:x}", v],
+            (true, true) => format!["-{:x}", v.unsigned_abs()],
+        }
+    }
+
+    /// Memory as a grid of COLUMNS cells per row, starting at row top, with
+    /// each row labelled by the address of its first cell.
+    pub fn code_string(&self) -> StyledString {
+        let start = self.top * COLUMNS;
+        let end = start + ROWS * COLUMNS;
+        let values: Vec<String> = (start..end)
+            .map(|a| self.format_value(self.program.peek(a).unwrap_or_default()))
+            .collect();
+        let width = values.iter().map(|v| v.len()).max().unwrap_or(1);
+        let label_width = self.format_value(end - COLUMNS).len().max(4);
+        let ip = self.program.ip();
+        let len = self.cur_len() as Int;
+        let mut code = StyledString::new();
+        for (i, value) in values.iter().enumerate() {
+            let addr = start + i as Int;
+            if i as Int % COLUMNS == 0 {
+                if i > 0 {
+                    code.append_plain("\n");
+                }
+                code.append_plain(format![
+                    "{:0>w$}:",
+                    self.format_value(addr),
+                    w = label_width
+                ]);
+            }
+            let mut style = if addr >= ip && addr < ip.saturating_add(len) {
+                Style::from(Effect::Reverse)
+            } else {
+                Style::none()
+            };
+            if addr == self.cursor {
+                style = style.combine(Effect::Bold).combine(ColorStyle::new(
+                    Color::Dark(BaseColor::Black),
+                    Color::Light(BaseColor::Yellow),
+                ));
+            }
+            if self.watched(addr) {
+                style = style.combine(Effect::Underline);
+            }
+            let cell = if self.breakpoints.contains(&addr) {
+                style = style.combine(Color::Light(BaseColor::Red));
+                format!["*{}", value]
+            } else {
+                value.clone()
+            };
+            code.append_plain(" ".repeat(width + 1 - cell.len()));
+            code.append(StyledString::styled(cell, style));
+        }
+        code
+    }
+
+    /// The cells around rel_base, where relative parameters usually point.
+    pub fn rel_base_string(&self) -> String {
+        let rb = self.program.rel_base();
+        let mut s = "rel_base neighborhood:\n".to_string();
+        for offset in -REL_RADIUS..=REL_RADIUS {
+            let (addr, value) = match rb.checked_add(offset) {
+                Some(addr) => (
+                    addr.to_string(),
+                    match self.program.peek(addr) {
+                        Ok(v) => self.format_value(v),
+                        Err(_) => "-".to_string(),
+                    },
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            s += &format!["rb{:<+3} [{}] = {}\n", offset, addr, value];
+        }
+        s
+    }
+
+    /// Select the cell at addr, scrolling the Code panel to show it.
+    pub fn goto(&mut self, addr: Int) {
+        self.cursor = addr.clamp(0, (MAX_TOP + ROWS) * COLUMNS - 1);
+        let row = self.cursor / COLUMNS;
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + ROWS {
+            self.top = row - ROWS + 1;
+        }
+    }
+
+    /// Move the cursor by delta cells.
+    pub fn move_cursor(&mut self, delta: Int) {
+        self.goto(self.cursor.saturating_add(delta));
+    }
+
+    /// Move the cursor to the instruction pointer.
+    fn follow_ip(&mut self) {
+        self.goto(self.program.ip());
+    }
+
+    /// Write value to the cell at the cursor.
+    pub fn edit(&mut self, value: &str) {
+        let value = value.trim();
+        let parsed = match value.strip_prefix("0x") {
+            Some(h) => Int::from_str_radix(h, 16),
+            None => value.parse(),
+        };
+        self.status = match parsed {
+            Ok(v) => match self.program.poke(self.cursor, v) {
+                Ok(_) => format!["set [{}] to {}", self.cursor, v],
+                Err(e) => e.to_string(),
+            },
+            Err(_) => format!["invalid value \"{}\"", value],
+        };
+    }
+
+    pub fn stack_string(&self) -> StyledString {
+        let mut code = StyledString::new();
+        for entry in self.program.stack.iter() {
+            code.append(StyledString::plain(format![
+                "{:<4}: {:<4}: {}\n",
+                entry.address,
+                entry.value,
+                entry
+                    .value
+                    .op()
+                    .map_or("???".to_string(), |op| op.to_string())
+            ]));
+        }
+        code
+    }
+
+    /// Describe the instruction at the instruction pointer: each parameter's
+    /// mode, raw value, resolved address and value, and the effect of
+    /// executing it.
+    pub fn instruction_string(&self) -> String {
+        match self.describe() {
+            Ok(s) => s,
+            Err(e) => format!["ip:       {}\n{}", self.program.ip(), e],
+        }
+    }
+
+    fn describe(&self) -> Result<String, IntcodeError> {
+        let p = &self.program;
+        let ip = p.ip();
+        let (v, op, modes) = p.decode(ip)?;
+        let mut s = format![
+            "ip:       {}\nrel_base: {}\ninstr:    {} {}\n",
+            ip,
+            p.rel_base(),
+            v,
+            op.to_string().trim_end()
+        ];
+        for n in 1..op.len() {
+            let mode = &modes[n - 1];
+            let raw = p.peek(p.offset(ip, ip, n as Int)?)?;
+            s += &format![
+                "param {}:  {:<9} raw {:<8}",
+                n,
+                match mode {
+                    Mode::Pointer => "position",
+                    Mode::Value => "immediate",
+                    Mode::Relative => "relative",
+                },
+                raw
+            ];
+            if let Some(addr) = p.paddr(mode, raw)? {
+                s += &format![" addr {:<8}", addr];
+            } else {
+                s += &format![" {:13}", ""];
+            }
+            if op.writes() == Some(n) {
+                s += " (write)\n";
+            } else {
+                s += &format![" value {}\n", p.pval(mode, raw)?];
+            }
+        }
+        let arg = |n| p.arg(&modes, ip, n);
+        s += &match op {
+            Operation::End => "halts".to_string(),
+            Operation::Add => format![
+                "writes {} to [{}]",
+                p.arith(ip, arg(1)?, ArithOp::Add, arg(2)?)?,
+                p.dest(&modes, ip, 3)?
+            ],
+            Operation::Mul => format![
+                "writes {} to [{}]",
+                p.arith(ip, arg(1)?, ArithOp::Mul, arg(2)?)?,
+                p.dest(&modes, ip, 3)?
+            ],
+            Operation::LessThan => format![
+                "writes {} to [{}]",
+                (arg(1)? < arg(2)?) as Int,
+                p.dest(&modes, ip, 3)?
+            ],
+            Operation::EqualTo => format![
+                "writes {} to [{}]",
+                (arg(1)? == arg(2)?) as Int,
+                p.dest(&modes, ip, 3)?
+            ],
+            Operation::Input => format!["writes input to [{}]", p.dest(&modes, ip, 1)?],
+            Operation::Output => format!["outputs {}", arg(1)?],
+            Operation::JumpNotZero | Operation::JumpZero => {
+                if (arg(1)? != 0) == (op == Operation::JumpNotZero) {
+                    format!["jumps to {}", arg(2)?]
+                } else {
+                    format!["continues to {}", p.offset(ip, ip, 3)?]
+                }
+            }
+            Operation::RelBase => format![
+                "sets rel_base to {}",
+                p.arith(ip, p.rel_base(), ArithOp::Add, arg(1)?)?
+            ],
+        };
+        Ok(s)
+    }
+
+    /// Whether the next instruction needs input that isn't queued.
+    pub fn needs_input(&self) -> bool {
+        self.queue.is_empty() && self.cur_val().op() == Some(Operation::Input)
+    }
+
+    /// Execute one instruction, taking input from the queue and capturing
+    /// output.
+    fn execute(&mut self) -> Result<(), IntcodeError> {
+        let input = if self.cur_val().op() == Some(Operation::Input) {
+            self.queue.pop_front()
+        } else {
+            None
+        };
+        self.execute_with(input)
+    }
+
+    fn execute_with(&mut self, input: Option<Int>) -> Result<(), IntcodeError> {
+        let mut port: VecDeque<Int> = input.into_iter().collect();
+        let result = self.program.step(None, &mut port, &mut self.outputs);
+        match (&result, input) {
+            (Ok(()), Some(v)) => self.inputs.push(v),
+            (Err(_), Some(v)) => self.queue.push_front(v),
+            _ => (),
+        }
+        result
+    }
+
+    pub fn step(&mut self) {
+        self.status = match self.execute() {
+            Ok(()) => self.hits_string(),
+            Err(e) => e.to_string(),
+        };
+        self.follow_ip();
+    }
+
+    pub fn step_input(&mut self, input: &str) {
+        let result =
+            match input.trim().parse::<Int>() {
+                Ok(v) => self.execute_with(Some(v)),
+                Err(_) => Err(PortError::Invalid(input.trim().to_string())
+                    .at(self.program.ip(), self.cur_val())),
+            };
+        self.status = match result {
+            Ok(()) => self.hits_string(),
+            Err(e) => e.to_string(),
+        };
+        self.follow_ip();
+    }
+
+    /// Add the values in an input script to the end of the input queue.
+    pub fn queue_input(&mut self, script: &str) {
+        self.status = match parse_script(script) {
+            Ok(values) => {
+                self.queue.extend(values.iter());
+                format!["{} values queued", self.queue.len()]
+            }
+            Err(e) => e,
+        };
+    }
+
+    /// The captured output, inputs used so far and queued input.
+    pub fn io_string(&self) -> String {
+        let output = if self.ascii {
+            let mut o = AsciiOutput::new(Vec::new());
+            for v in self.outputs.iter() {
+                // writing to a vector can't fail
+                o.write(*v).unwrap();
+            }
+            String::from_utf8_lossy(&o.into_inner()).into_owned()
+        } else {
+            join(self.outputs.iter())
+        };
+        format![
+            "queued: {}\ninputs: {}\noutput:\n{}",
+            join(self.queue.iter()),
+            join(self.inputs.iter()),
+            output
+        ]
+    }
+
+    /// Describe the watched accesses made by the last instruction.
+    fn hits_string(&self) -> String {
+        let hits: Vec<String> = self
+            .program
+            .watch_hits()
+            .iter()
+            .map(|h| format!["watch {}", h])
+            .collect();
+        hits.join("; ")
+    }
+
+    /// Watch the addresses start to end for reads and writes, or stop
+    /// watching them if already watched. Returns whether they are now
+    /// watched.
+    pub fn toggle_watch(&mut self, start: Int, end: Int) -> bool {
+        if self.program.unwatch(start, end) {
+            false
+        } else {
+            self.program.watch(start, end, Access::ReadWrite);
+            true
+        }
+    }
+
+    fn watched(&self, addr: Int) -> bool {
+        self.program
+            .watchpoints()
+            .iter()
+            .any(|w| addr >= w.start && addr <= w.end)
+    }
+
+    pub fn step_back(&mut self) {
+        self.status = match self.program.step_back() {
+            Some(e) => {
+                if e.output().is_some() {
+                    self.outputs.pop();
+                }
+                match e.input() {
+                    Some(i) => {
+                        self.inputs.pop();
+                        format!["undid input {} at {}", i, e.address()]
+                    }
+                    None => String::new(),
+                }
+            }
+            None => "no more history".to_string(),
+        };
+        self.follow_ip();
+    }
+
+    /// Set a breakpoint at addr, or clear it if already set. Returns whether
+    /// the breakpoint is now set.
+    pub fn toggle_breakpoint(&mut self, addr: Int) -> bool {
+        if self.breakpoints.remove(&addr) {
+            false
+        } else {
+            self.breakpoints.insert(addr);
+            true
+        }
+    }
+
+    /// Step until reaching a breakpoint or an input instruction, or the
+    /// program halts or fails.
+    pub fn cont(&mut self) {
+        self.run_to_stop(None);
+        self.follow_ip();
+    }
+
+    /// Step up to limit instructions, stopping early where `cont` would.
+    fn run_to_stop(&mut self, limit: Option<usize>) {
+        let mut steps = 0;
+        loop {
+            if limit == Some(steps) {
+                self.status = self.hits_string();
+                return;
+            }
+            steps += 1;
+            if self.program.is_halted() {
+                self.status = "halted".to_string();
+                return;
+            }
+            if self.needs_input() {
+                self.status = format!["input required at {}", self.program.ip()];
+                return;
+            }
+            if let Err(e) = self.execute() {
+                self.status = e.to_string();
+                return;
+            }
+            if !self.program.watch_hits().is_empty() {
+                self.status = self.hits_string();
+                return;
+            }
+            if self.breakpoints.contains(&self.program.ip()) {
+                self.status = format!["breakpoint at {}", self.program.ip()];
+                return;
+            }
+        }
+    }
+
+    /// Reload the program, keeping breakpoints, watchpoints and queued input.
+    pub fn reset(&mut self) {
+        self.program.reset();
+        self.inputs.clear();
+        self.outputs.clear();
+        self.follow_ip();
+    }
+
+    /// Parse and run a console command, leaving its result in status.
+    pub fn command(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        if self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+        }
+        self.history_pos = self.history.len();
+        let command = match line.parse::<Command>() {
+            Ok(c) => c,
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        match command {
+            Command::Break(addr) => {
+                let addr = addr.unwrap_or(self.cursor);
+                self.breakpoints.insert(addr);
+                self.status = format!["breakpoint set at {}", addr];
+            }
+            Command::Delete(Some(addr)) => {
+                self.status = if self.breakpoints.remove(&addr) {
+                    format!["breakpoint cleared at {}", addr]
+                } else {
+                    format!["no breakpoint at {}", addr]
+                };
+            }
+            Command::Delete(None) => {
+                self.status = format!["{} breakpoints cleared", self.breakpoints.len()];
+                self.breakpoints.clear();
+            }
+            Command::Step(n) => {
+                self.run_to_stop(Some(n));
+                self.follow_ip();
+            }
+            Command::Continue => self.cont(),
+            Command::Print(start, end) if end.checked_sub(start).is_none_or(|n| n >= MAX_PRINT) => {
+                self.status = format!["range too large, print at most {} cells", MAX_PRINT];
+            }
+            Command::Print(start, end) => {
+                self.status = match (start..=end)
+                    .map(|a| self.program.peek(a).map(|v| self.format_value(v)))
+                    .collect::<Result<Vec<String>, _>>()
+                {
+                    Ok(values) if start == end => format!["[{}] = {}", start, values[0]],
+                    Ok(values) => format!["[{}-{}] = {}", start, end, values.join(", ")],
+                    Err(e) => e.to_string(),
+                };
+            }
+            Command::Set(addr, value) => {
+                self.status = match self.program.poke(addr, value) {
+                    Ok(_) => format!["set [{}] to {}", addr, value],
+                    Err(e) => e.to_string(),
+                };
+            }
+            Command::Input(script) => self.queue_input(&script),
+            Command::Reset => {
+                self.reset();
+                self.status = "reset".to_string();
+            }
+            Command::Goto(addr) => {
+                self.goto(addr);
+                self.status = String::new();
+            }
+            Command::Watch(start, end) => {
+                self.status = if self.toggle_watch(start, end) {
+                    format!["watching {}-{}", start, end]
+                } else {
+                    format!["stopped watching {}-{}", start, end]
+                };
+            }
+            Command::Help => self.status = HELP.to_string(),
+        }
+    }
+
+    /// The previous command in the history, for recalling with up.
+    pub fn history_prev(&mut self) -> Option<&str> {
+        self.history_pos = self.history_pos.checked_sub(1)?;
+        Some(&self.history[self.history_pos])
+    }
+
+    /// The next command in the history, or an empty line past the end.
+    pub fn history_next(&mut self) -> &str {
+        self.history_pos = (self.history_pos + 1).min(self.history.len());
+        self.history
+            .get(self.history_pos)
+            .map_or("", String::as_str)
+    }
+}
+
+const HELP: &str = "quit, break [ADDR], delete [ADDR], step [N], continue, print ADDR[-END], \
+                    set ADDR=VAL, input VALUES, reset, goto ADDR, watch ADDR[-END]";
+
+/// A command typed into the debugger's console.
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// set a breakpoint, at the cursor if no address is given
+    Break(Option<Int>),
+    /// clear a breakpoint, or all of them if no address is given
+    Delete(Option<Int>),
+    Step(usize),
+    Continue,
+    Print(Int, Int),
+    Set(Int, Int),
+    /// queue an input script
+    Input(String),
+    Reset,
+    /// move the cursor
+    Goto(Int),
+    /// toggle a watchpoint
+    Watch(Int, Int),
+    Help,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Command, String> {
+        let (name, arg) = match line.trim().split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line.trim(), ""),
+        };
+        let addr = |s: &str| {
+            s.trim()
+                .parse::<Int>()
+                .map_err(|_| format!["invalid address \"{}\"", s.trim()])
+        };
+        let optional = |s: &str| {
+            if s.is_empty() {
+                Ok(None)
+            } else {
+                addr(s).map(Some)
+            }
+        };
+        let range = |s: &str| parse_range(s).ok_or(format!["invalid address \"{}\"", s]);
+        Ok(match name {
+            "b" | "break" => Command::Break(optional(arg)?),
+            "d" | "delete" => Command::Delete(optional(arg)?),
+            "s" | "step" if arg.is_empty() => Command::Step(1),
+            "s" | "step" => Command::Step(
+                arg.parse()
+                    .map_err(|_| format!["invalid count \"{}\"", arg])?,
+            ),
+            "c" | "continue" => Command::Continue,
+            "p" | "print" => {
+                let (start, end) = range(arg)?;
+                Command::Print(start, end)
+            }
+            "set" => {
+                let (a, v) = arg.split_once('=').ok_or("usage: set ADDR=VAL")?;
+                let v = v
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!["invalid value \"{}\"", v.trim()])?;
+                Command::Set(addr(a)?, v)
+            }
+            "i" | "input" => Command::Input(arg.to_string()),
+            "reset" => Command::Reset,
+            "g" | "goto" => Command::Goto(addr(arg)?),
+            "w" | "watch" => {
+                let (start, end) = range(arg)?;
+                Command::Watch(start, end)
+            }
+            "h" | "help" => Command::Help,
+            _ => return Err(format!["unknown command \"{}\"", name]),
+        })
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a Int>) -> String {
+    values
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Parse an input script: each line is either a list of numbers separated by
+/// commas or whitespace, or text starting with `"`, which is converted to its
+/// character codes followed by a newline (10). An optional closing `"` is
+/// dropped.
+pub fn parse_script(script: &str) -> Result<Vec<Int>, String> {
+    let mut values = Vec::new();
+    for line in script.lines() {
+        if let Some(text) = line.trim_start().strip_prefix('"') {
+            let text = text.strip_suffix('"').unwrap_or(text);
+            values.extend(text.chars().map(|c| c as Int));
+            values.push(10);
+            continue;
+        }
+        for word in line.split(|c: char| c == ',' || c.is_whitespace()) {
+            if !word.is_empty() {
+                values.push(
+                    word.parse()
+                        .map_err(|_| format!["invalid input \"{}\"", word])?,
+                );
+            }
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test_debugger {
+    use super::*;
+    use std::io;
+
+    fn debugger(code: &str) -> Debugger {
+        Debugger::new(Program::new(io::Cursor::new(code)), &[])
+    }
+
+    #[test]
+    fn test_cont_breakpoint() {
+        let mut d = debugger("1101,1,1,0,1101,2,2,0,3,0,99");
+        assert!(d.toggle_breakpoint(4));
+        d.cont();
+        assert_eq!(d.program.ip(), 4);
+        assert_eq!(d.status, "breakpoint at 4");
+        d.cont();
+        assert_eq!(d.program.ip(), 8);
+        assert_eq!(d.status, "input required at 8");
+        d.step_input("5");
+        d.cont();
+        assert!(d.program.is_halted());
+        assert_eq!(d.status, "halted");
+        assert!(!d.toggle_breakpoint(4));
+    }
+
+    #[test]
+    fn test_cont_watch() {
+        let mut d = debugger("1101,1,1,20,1101,2,2,21,1001,20,5,22,99");
+        assert!(d.toggle_watch(20, 20));
+        d.cont();
+        assert_eq!(d.program.ip(), 4);
+        assert_eq!(d.status, "watch 0: write [20] 0 -> 2");
+        d.cont();
+        assert_eq!(d.program.ip(), 12);
+        assert_eq!(d.status, "watch 8: read [20] = 2");
+        assert!(!d.toggle_watch(20, 20));
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut d = debugger("3,0,99");
+        d.step_back();
+        assert_eq!(d.status, "no more history");
+        d.step_input("7");
+        d.step_back();
+        assert_eq!(d.status, "undid input 7 at 0");
+        assert_eq!(d.program.peek(0).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_instruction_string() {
+        let mut d = debugger("109,10,21001,6,-4,1,99");
+        assert_eq!(
+            d.instruction_string(),
+            "ip:       0\n\
+             rel_base: 0\n\
+             instr:    109 REL\n\
+             param 1:  immediate raw 10                     value 10\n\
+             sets rel_base to 10"
+        );
+        d.step();
+        assert_eq!(
+            d.instruction_string(),
+            "ip:       2\n\
+             rel_base: 10\n\
+             instr:    21001 ADD\n\
+             param 1:  position  raw 6        addr 6        value 99\n\
+             param 2:  immediate raw -4                     value -4\n\
+             param 3:  relative  raw 1        addr 11       (write)\n\
+             writes 95 to [11]"
+        );
+    }
+
+    #[test]
+    fn test_code_string() {
+        let mut d = debugger("1101,1,1,20,99");
+        d.toggle_breakpoint(4);
+        let code = d.code_string();
+        let lines: Vec<&str> = code.source().lines().collect();
+        assert_eq!(lines.len(), ROWS as usize);
+        assert_eq!(
+            lines[0],
+            "0000: 1101    1    1   20  *99    0    0    0    0    0"
+        );
+        assert_eq!(
+            lines[1],
+            "0010:    0    0    0    0    0    0    0    0    0    0"
+        );
+        d.hex = true;
+        assert_eq!(
+            d.code_string().source().lines().next().unwrap(),
+            "0000: 44d   1   1  14 *63   0   0   0   0   0"
+        );
+    }
+
+    #[test]
+    fn test_goto_edit() {
+        let mut d = debugger("1101,1,1,20,99");
+        d.goto(1000);
+        assert_eq!(d.top, 1000 / COLUMNS - ROWS + 1);
+        assert!(d
+            .code_string()
+            .source()
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("1000:"));
+        d.edit("0x2a");
+        assert_eq!(d.status, "set [1000] to 42");
+        assert_eq!(d.program.peek(1000).unwrap(), 42);
+        d.edit("x");
+        assert_eq!(d.status, "invalid value \"x\"");
+        d.move_cursor(-2000);
+        assert_eq!((d.cursor, d.top), (0, 0));
+        d.goto(Int::MAX);
+        assert_eq!(d.top, MAX_TOP);
+        assert_eq!(d.cursor, (MAX_TOP + ROWS) * COLUMNS - 1);
+        assert!(d
+            .code_string()
+            .source()
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with(&format!["{}:", (MAX_TOP + ROWS - 1) * COLUMNS]));
+        d.goto(0);
+        d.step();
+        assert_eq!(d.cursor, 4);
+    }
+
+    #[test]
+    fn test_rel_base_string() {
+        let mut d = debugger("109,2,99");
+        d.step();
+        assert_eq!(
+            d.rel_base_string(),
+            "rel_base neighborhood:\n\
+             rb-4  [-2] = -\n\
+             rb-3  [-1] = -\n\
+             rb-2  [0] = 109\n\
+             rb-1  [1] = 2\n\
+             rb+0  [2] = 99\n\
+             rb+1  [3] = 0\n\
+             rb+2  [4] = 0\n\
+             rb+3  [5] = 0\n\
+             rb+4  [6] = 0\n"
+        );
+        // the neighborhood may run past the end of Int
+        let mut d = debugger("109,9223372036854775807,99");
+        d.step();
+        assert!(d
+            .rel_base_string()
+            .ends_with("rb+0  [9223372036854775807] = 0\nrb+1  [-] = -\nrb+2  [-] = -\nrb+3  [-] = -\nrb+4  [-] = -\n"));
+    }
+
+    #[test]
+    fn test_parse_script() {
+        assert_eq!(parse_script("1, 2\n-3 4").unwrap(), vec![1, 2, -3, 4]);
+        assert_eq!(parse_script("\"Hi\"\n5").unwrap(), vec![72, 105, 10, 5]);
+        assert_eq!(parse_script("1,x").unwrap_err(), "invalid input \"x\"");
+    }
+
+    #[test]
+    fn test_io() {
+        let mut d = debugger("3,0,3,0,4,0,104,72,104,105,99");
+        d.queue_input("7");
+        assert_eq!(d.status, "1 values queued");
+        d.cont();
+        assert_eq!(d.status, "input required at 2");
+        d.step_input("x");
+        assert_eq!(d.status, "2: invalid input \"x\" for instruction 3");
+        d.step_input("72");
+        d.cont();
+        assert_eq!(d.status, "halted");
+        assert_eq!(
+            d.io_string(),
+            "queued: \ninputs: 7, 72\noutput:\n72, 72, 105"
+        );
+        d.ascii = true;
+        assert_eq!(d.io_string(), "queued: \ninputs: 7, 72\noutput:\nHHi");
+        for _ in 0..3 {
+            d.step_back();
+        }
+        assert_eq!(d.io_string(), "queued: \ninputs: 7, 72\noutput:\nH");
+        d.step_back();
+        d.step_back();
+        assert_eq!(d.status, "undid input 72 at 2");
+        assert_eq!(d.io_string(), "queued: \ninputs: 7\noutput:\n");
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!("break 12".parse(), Ok(Command::Break(Some(12))));
+        assert_eq!("b".parse(), Ok(Command::Break(None)));
+        assert_eq!("step".parse(), Ok(Command::Step(1)));
+        assert_eq!("s 5".parse(), Ok(Command::Step(5)));
+        assert_eq!("print 3-5".parse(), Ok(Command::Print(3, 5)));
+        assert_eq!("set 7 = -1".parse(), Ok(Command::Set(7, -1)));
+        assert_eq!(
+            "input 1, 2,3".parse(),
+            Ok(Command::Input("1, 2,3".to_string()))
+        );
+        assert_eq!(
+            "set 7".parse::<Command>(),
+            Err("usage: set ADDR=VAL".to_string())
+        );
+        assert_eq!(
+            "frob".parse::<Command>(),
+            Err("unknown command \"frob\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_command() {
+        let mut d = debugger("3,9,1001,9,1,9,4,9,99,0");
+        d.command("input 41");
+        d.command("break 6");
+        d.command("continue");
+        assert_eq!(d.status, "breakpoint at 6");
+        d.command("print 9");
+        assert_eq!(d.status, "[9] = 42");
+        d.command("set 9=7");
+        d.command("p 8-9");
+        assert_eq!(d.status, "[8-9] = 99, 7");
+        d.command("print 0-100000000000");
+        assert_eq!(d.status, "range too large, print at most 1000 cells");
+        d.command("step 5");
+        assert_eq!(d.status, "halted");
+        assert_eq!(d.outputs, vec![7]);
+        d.command("reset");
+        assert_eq!(d.program.ip(), 0);
+        assert!(d.outputs.is_empty());
+        d.command("delete");
+        assert_eq!(d.status, "1 breakpoints cleared");
+        d.command("step 2");
+        assert_eq!(d.status, "input required at 0");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut d = debugger("99");
+        assert_eq!(d.history_prev(), None);
+        d.command("step");
+        d.command("print 0");
+        d.command("print 0");
+        assert_eq!(d.history_prev(), Some("print 0"));
+        assert_eq!(d.history_prev(), Some("step"));
+        assert_eq!(d.history_prev(), None);
+        assert_eq!(d.history_next(), "print 0");
+        assert_eq!(d.history_next(), "");
+    }
+}
@@ -0,0 +1,286 @@
+//! Interactive terminal UI for the debugger.
+
+use super::{parse_range, Debugger, COLUMNS, ROWS};
+use crate::{Int, Program};
+use cursive::event::{Event, Key};
+use cursive::view::{scroll::ScrollStrategy, Nameable, SizeConstraint};
+use cursive::views::{
+    Dialog, EditView, LinearLayout, OnEventView, Panel, ResizedView, ScrollView, TextView,
+};
+use cursive::Cursive;
+use std::io;
+
+/// Toggle the watchpoint on the address or range typed in addr.
+fn toggle_watch(siv: &mut Cursive, addr: &str) {
+    let d = siv.user_data::<Debugger>().unwrap();
+    d.status = match parse_range(addr) {
+        Some((start, end)) if d.toggle_watch(start, end) => {
+            format!["watching {}-{}", start, end]
+        }
+        Some((start, end)) => format!["stopped watching {}-{}", start, end],
+        None => format!["invalid address \"{}\"", addr.trim()],
+    };
+}
+
+/// Toggle the breakpoint at the address typed in addr.
+fn toggle_breakpoint(siv: &mut Cursive, addr: &str) {
+    let d = siv.user_data::<Debugger>().unwrap();
+    d.status = match addr.trim().parse::<Int>() {
+        Ok(a) if d.toggle_breakpoint(a) => format!["breakpoint set at {}", a],
+        Ok(a) => format!["breakpoint cleared at {}", a],
+        Err(_) => format!["invalid address \"{}\"", addr.trim()],
+    };
+}
+
+/// Show a dialog asking for a line of text, passed to action on submit.
+fn prompt(siv: &mut Cursive, title: &str, action: fn(&mut Cursive, &str)) {
+    siv.add_layer(
+        Dialog::new()
+            .title(title)
+            .padding_lrtb(1, 1, 1, 0)
+            .content(
+                EditView::new()
+                    .on_submit(move |s, text| {
+                        s.pop_layer();
+                        action(s, text);
+                        update(s);
+                    })
+                    .with_name("prompt"),
+            )
+            .button("Ok", move |s| {
+                let text = s
+                    .call_on_name("prompt", |view: &mut EditView| view.get_content())
+                    .unwrap();
+                s.pop_layer();
+                action(s, &text);
+                update(s);
+            }),
+    );
+}
+
+/// Run the command typed in the console, which keeps focus for the next one.
+fn run_command(siv: &mut Cursive, line: &str) {
+    if line.trim() == "q" || line.trim() == "quit" {
+        siv.quit();
+        return;
+    }
+    siv.user_data::<Debugger>().unwrap().command(line);
+    siv.call_on_name("command", |v: &mut EditView| v.set_content(""));
+    update(siv);
+}
+
+fn update(siv: &mut Cursive) {
+    let d = siv.user_data::<Debugger>().unwrap();
+    let code = d.code_string();
+    let stack = d.stack_string();
+    let status = d.status.clone();
+    let instruction = d.instruction_string() + "\n\n" + &d.rel_base_string();
+    let io = d.io_string();
+    siv.call_on_name("code", |v: &mut TextView| {
+        v.set_content(code);
+    });
+    siv.call_on_name("instruction", |v: &mut TextView| {
+        v.set_content(instruction);
+    });
+    siv.call_on_name("status", |v: &mut TextView| {
+        v.set_content(status);
+    });
+    siv.call_on_name("stack", |v: &mut TextView| {
+        v.set_content(stack);
+    });
+    siv.call_on_name("io", |v: &mut TextView| {
+        v.set_content(io);
+    });
+}
+
+/// Debug prog in the terminal UI, with the values in input queued for its
+/// input instructions.
+pub fn debug(prog: Program, input: &[Int]) -> io::Result<()> {
+    let d = Debugger::new(prog, input);
+
+    let mut siv = cursive::default();
+    siv.add_global_callback('q', |s| s.quit());
+    siv.add_global_callback(':', |s| {
+        s.call_on_name("command", |v: &mut EditView| v.enable());
+        s.focus_name("command").unwrap();
+    });
+    siv.add_global_callback('n', |s| {
+        let d = s.user_data::<Debugger>().unwrap();
+        if d.needs_input() {
+            s.add_layer(
+                Dialog::new()
+                    .title("Input")
+                    .padding_lrtb(1, 1, 1, 0)
+                    .content(
+                        EditView::new()
+                            .on_submit(|s, input| {
+                                let d = s.user_data::<Debugger>().unwrap();
+                                d.step_input(input);
+                                s.pop_layer();
+                                update(s);
+                            })
+                            .with_name("input"),
+                    )
+                    .button("Ok", |s| {
+                        let input = s
+                            .call_on_name("input", |view: &mut EditView| view.get_content())
+                            .unwrap();
+                        let d = s.user_data::<Debugger>().unwrap();
+                        d.step_input(&input);
+                        s.pop_layer();
+                        update(s);
+                    }),
+            );
+        } else {
+            d.step();
+        }
+        update(s);
+    });
+    siv.add_global_callback('b', |s| {
+        s.user_data::<Debugger>().unwrap().step_back();
+        update(s);
+    });
+    siv.add_global_callback('c', |s| {
+        s.user_data::<Debugger>().unwrap().cont();
+        update(s);
+    });
+    siv.add_global_callback('t', |s| {
+        let d = s.user_data::<Debugger>().unwrap();
+        let cursor = d.cursor;
+        d.toggle_breakpoint(cursor);
+        update(s);
+    });
+    siv.add_global_callback('w', |s| {
+        prompt(s, "Toggle watchpoint at address or range", toggle_watch)
+    });
+    siv.add_global_callback('B', |s| {
+        prompt(s, "Toggle breakpoint at address", toggle_breakpoint)
+    });
+    siv.add_global_callback('g', |s| {
+        prompt(s, "Go to address", |s, addr| {
+            let d = s.user_data::<Debugger>().unwrap();
+            match addr.trim().parse::<Int>() {
+                Ok(a) => d.goto(a),
+                Err(_) => d.status = format!["invalid address \"{}\"", addr.trim()],
+            }
+        })
+    });
+    siv.add_global_callback('e', |s| {
+        let cursor = s.user_data::<Debugger>().unwrap().cursor;
+        prompt(s, &format!["Set value at {}", cursor], |s, value| {
+            s.user_data::<Debugger>().unwrap().edit(value)
+        })
+    });
+    siv.add_global_callback('i', |s| {
+        prompt(s, "Queue input (numbers, or \"text)", |s, script| {
+            s.user_data::<Debugger>().unwrap().queue_input(script)
+        })
+    });
+    siv.add_global_callback('a', |s| {
+        let d = s.user_data::<Debugger>().unwrap();
+        d.ascii = !d.ascii;
+        update(s);
+    });
+    siv.add_global_callback('x', |s| {
+        let d = s.user_data::<Debugger>().unwrap();
+        d.hex = !d.hex;
+        update(s);
+    });
+    for (keys, delta) in &[
+        ([Event::Key(Key::Left), Event::Char('h')], -1),
+        ([Event::Key(Key::Right), Event::Char('l')], 1),
+        ([Event::Key(Key::Up), Event::Char('k')], -COLUMNS),
+        ([Event::Key(Key::Down), Event::Char('j')], COLUMNS),
+        ([Event::Key(Key::PageUp), Event::Char('K')], -COLUMNS * ROWS),
+        (
+            [Event::Key(Key::PageDown), Event::Char('J')],
+            COLUMNS * ROWS,
+        ),
+    ] {
+        let delta = *delta;
+        for key in keys.iter().cloned() {
+            siv.add_global_callback(key, move |s| {
+                s.user_data::<Debugger>().unwrap().move_cursor(delta);
+                update(s);
+            });
+        }
+    }
+
+    siv.add_fullscreen_layer(
+        LinearLayout::vertical()
+            .child(ResizedView::new(
+                SizeConstraint::Full,
+                SizeConstraint::Fixed(30),
+                Panel::new(TextView::new("").with_name("code")).title("Code"),
+            ))
+            .child(ResizedView::new(
+                SizeConstraint::Full,
+                SizeConstraint::Full,
+                LinearLayout::horizontal()
+                    .child(ResizedView::new(
+                        SizeConstraint::Full,
+                        SizeConstraint::Full,
+                        Panel::new(
+                            ScrollView::new(TextView::new("[empty]").with_name("stack"))
+                                .scroll_strategy(ScrollStrategy::StickToBottom),
+                        )
+                        .title("Stack"),
+                    ))
+                    .child(ResizedView::new(
+                        SizeConstraint::Full,
+                        SizeConstraint::Full,
+                        Panel::new(TextView::new("").with_name("instruction")).title("Instruction"),
+                    ))
+                    .child(ResizedView::new(
+                        SizeConstraint::Full,
+                        SizeConstraint::Full,
+                        Panel::new(
+                            ScrollView::new(TextView::new("").with_name("io"))
+                                .scroll_strategy(ScrollStrategy::StickToBottom),
+                        )
+                        .title("I/O"),
+                    )),
+            ))
+            .child(TextView::new(
+                "q:quit n:next b:back c:continue t:breakpoint B:breakpoint at w:watch \
+                 g:goto e:edit x:hex/dec hjkl:move i:queue input a:ascii :command",
+            ))
+            .child(
+                LinearLayout::horizontal()
+                    .child(TextView::new(":"))
+                    .child(ResizedView::new(
+                        SizeConstraint::Full,
+                        SizeConstraint::Fixed(1),
+                        OnEventView::new(
+                            EditView::new()
+                                .disabled()
+                                .on_submit(run_command)
+                                .with_name("command"),
+                        )
+                        .on_pre_event(Key::Up, |s| {
+                            let d = s.user_data::<Debugger>().unwrap();
+                            if let Some(line) = d.history_prev().map(str::to_string) {
+                                s.call_on_name("command", |v: &mut EditView| v.set_content(line));
+                            }
+                        })
+                        .on_pre_event(Key::Down, |s| {
+                            let d = s.user_data::<Debugger>().unwrap();
+                            let line = d.history_next().to_string();
+                            s.call_on_name("command", |v: &mut EditView| v.set_content(line));
+                        })
+                        .on_pre_event(Key::Esc, |s| {
+                            s.call_on_name("command", |v: &mut EditView| {
+                                v.set_content("");
+                                v.disable();
+                            });
+                        }),
+                    )),
+            )
+            .child(TextView::new("").with_name("status")),
+    );
+
+    siv.set_user_data(d);
+    update(&mut siv);
+    siv.run();
+    Ok(())
+}
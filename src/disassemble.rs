@@ -0,0 +1,161 @@
+//! Disassembler producing readable listings of Intcode programs.
+
+use super::{Instruction, Int, Mode, Operation, Program};
+use std::fmt;
+
+enum Kind {
+    Instruction(Operation, Vec<Mode>),
+    Data,
+}
+
+/// A single line of a disassembly listing: either one instruction with its
+/// parameters, or one word that does not decode as an instruction.
+pub struct Line {
+    address: Int,
+    words: Vec<Int>,
+    kind: Kind,
+}
+
+impl Line {
+    /// Decode the line starting at addr.
+    fn decode(prog: &Program, addr: Int) -> Line {
+        let v = prog.peek(addr).unwrap_or_default();
+        if let (Some(op), Some(modes)) = (v.op(), v.modes()) {
+            let len = op.len();
+            let writes = op.writes();
+            // an instruction can't write to an immediate parameter, so this
+            // must be data
+            if writes.is_none_or(|n| modes[n - 1] != Mode::Value) {
+                let words = (addr..addr + len as Int)
+                    .map(|a| prog.peek(a).unwrap_or_default())
+                    .collect();
+                return Line {
+                    address: addr,
+                    words,
+                    kind: Kind::Instruction(op, modes),
+                };
+            }
+        }
+        Line {
+            address: addr,
+            words: vec![v],
+            kind: Kind::Data,
+        }
+    }
+
+    pub fn address(&self) -> Int {
+        self.address
+    }
+
+    /// The memory words making up this line.
+    pub fn words(&self) -> &[Int] {
+        &self.words
+    }
+
+    pub fn is_data(&self) -> bool {
+        matches!(self.kind, Kind::Data)
+    }
+
+    /// The operation and parameter modes, unless this is data.
+    pub(crate) fn instruction(&self) -> Option<(Operation, &[Mode])> {
+        match &self.kind {
+            Kind::Instruction(op, modes) => Some((*op, modes)),
+            Kind::Data => None,
+        }
+    }
+}
+
+/// Format a parameter according to its mode: `[n]` for position, `#n` for
+/// immediate and `rb+n` for relative.
+pub(crate) fn param(mode: &Mode, value: Int) -> String {
+    match mode {
+        Mode::Pointer => format!["[{}]", value],
+        Mode::Value => format!["#{}", value],
+        Mode::Relative if value < 0 => format!["rb{}", value],
+        Mode::Relative => format!["rb+{}", value],
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}: ", self.address)?;
+        match &self.kind {
+            Kind::Instruction(op, modes) => {
+                let params: Vec<String> = self.words[1..]
+                    .iter()
+                    .zip(modes)
+                    .map(|(v, m)| param(m, *v))
+                    .collect();
+                if params.is_empty() {
+                    write!(f, "{}", op)
+                } else {
+                    write!(f, "{} {}", op, params.join(", "))
+                }
+            }
+            Kind::Data => write!(f, "DATA {}", self.words[0]),
+        }
+    }
+}
+
+/// Disassemble the program image, walking from address 0 so instructions
+/// stay aligned, and return the lines starting in the range [start, end).
+pub fn disassemble(prog: &Program, start: Int, end: Int) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < end && addr < prog.mem.dense().len() as Int {
+        let line = Line::decode(prog, addr);
+        addr += line.words.len() as Int;
+        if line.address >= start {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test_disassemble {
+    use super::*;
+    use std::io;
+
+    fn listing(code: &str, start: Int, end: Int) -> Vec<String> {
+        let prog = Program::new(io::Cursor::new(code));
+        disassemble(&prog, start, end)
+            .iter()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_modes() {
+        assert_eq!(
+            listing("21001,100,5,3,109,-2,204,-1,99", 0, 100),
+            vec![
+                "0000: ADD [100], #5, rb+3",
+                "0004: REL #-2",
+                "0006: OUT rb-1",
+                "0008: END",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(
+            listing("1105,1,4,42,11101,1,2,3,1234", 0, 100),
+            vec![
+                "0000: JNZ #1, #4",
+                "0003: DATA 42",
+                "0004: DATA 11101",
+                "0005: ADD [2], [3], [1234]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(
+            listing("1,0,0,0,2,0,0,0,99", 1, 8),
+            vec!["0004: MUL [0], [0], [0]"]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use super::{ArithOp, Int};
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Errors raised while executing an Intcode program.
+///
+/// Faults in the program itself carry the address of the faulting instruction
+/// and the instruction word found there; `Io` is reserved for failures of the
+/// underlying reader or writer.
+#[derive(Debug)]
+pub enum IntcodeError {
+    /// the opcode (lowest two digits) of the instruction is not known
+    InvalidOpcode { address: Int, instruction: Int },
+    /// one of the parameter mode digits is not 0, 1 or 2
+    InvalidMode { address: Int, instruction: Int },
+    /// memory was accessed at a negative address
+    NegativeAddress { address: Int },
+    /// writing to address would allocate more memory than the limit allows
+    MemoryLimit { address: Int, limit: usize },
+    /// an instruction tried to write to an immediate-mode parameter
+    WriteToImmediate { address: Int, instruction: Int },
+    /// an input instruction was reached but no more input is available
+    InputExhausted { address: Int, instruction: Int },
+    /// the input could not be parsed as an integer
+    InvalidInput {
+        address: Int,
+        instruction: Int,
+        input: String,
+    },
+    /// the input or output channel was disconnected
+    ChannelClosed { address: Int, instruction: Int },
+    /// arithmetic for the instruction at address overflowed with
+    /// `Overflow::Error`, or an address it computed overflowed
+    Overflow {
+        address: Int,
+        instruction: Int,
+        lhs: Int,
+        op: ArithOp,
+        rhs: Int,
+    },
+    /// the jump at address returned the program to a state it was in before,
+    /// with no input or output since, so it will never halt; start and end
+    /// are the lowest and highest addresses of the instructions in the loop
+    InfiniteLoop {
+        address: Int,
+        instruction: Int,
+        start: Int,
+        end: Int,
+    },
+    /// reading input or writing output failed
+    Io(io::Error),
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntcodeError::InvalidOpcode {
+                address,
+                instruction,
+            } => write!(f, "{}: unknown instruction {}", address, instruction),
+            IntcodeError::InvalidMode {
+                address,
+                instruction,
+            } => write!(
+                f,
+                "{}: unknown mode in instruction {}",
+                address, instruction
+            ),
+            IntcodeError::NegativeAddress { address } => {
+                write!(f, "access to negative address {}", address)
+            }
+            IntcodeError::MemoryLimit { address, limit } => write!(
+                f,
+                "write to address {} exceeds memory limit of {} cells",
+                address, limit
+            ),
+            IntcodeError::WriteToImmediate {
+                address,
+                instruction,
+            } => write!(
+                f,
+                "{}: instruction {} writes to an immediate parameter",
+                address, instruction
+            ),
+            IntcodeError::InputExhausted {
+                address,
+                instruction,
+            } => write!(
+                f,
+                "{}: instruction {} requires input but none is available",
+                address, instruction
+            ),
+            IntcodeError::InvalidInput {
+                address,
+                instruction,
+                input,
+            } => write!(
+                f,
+                "{}: invalid input \"{}\" for instruction {}",
+                address, input, instruction
+            ),
+            IntcodeError::ChannelClosed {
+                address,
+                instruction,
+            } => write!(
+                f,
+                "{}: channel closed during instruction {}",
+                address, instruction
+            ),
+            IntcodeError::Overflow {
+                address,
+                instruction,
+                lhs,
+                op,
+                rhs,
+            } => write!(
+                f,
+                "{}: instruction {} overflows computing {} {} {}",
+                address, instruction, lhs, op, rhs
+            ),
+            IntcodeError::InfiniteLoop {
+                address,
+                instruction,
+                start,
+                end,
+            } => write!(
+                f,
+                "{}: instruction {} repeats an earlier state; infinite loop in {}-{}",
+                address, instruction, start, end
+            ),
+            IntcodeError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for IntcodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            IntcodeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IntcodeError {
+    fn from(e: io::Error) -> Self {
+        IntcodeError::Io(e)
+    }
+}
@@ -0,0 +1,449 @@
+//! Stub implementing a subset of the GDB remote serial protocol, so GDB and
+//! other front ends that speak it can debug Intcode programs over TCP.
+//!
+//! Memory is presented to the client as bytes, with each cell stored as 8
+//! little-endian bytes at byte address `cell * 8`. Register 0 is the
+//! instruction pointer, also as a byte address so it matches breakpoint
+//! addresses, and register 1 is the relative base. Both are 64 bits wide.
+//!
+//! Supported packets: `?`, `g`, `G`, `p`, `P`, `m`, `M`, `s`, `c`, `Z0`, `z0`,
+//! `k`, `D`, `qSupported`, `qAttached`, `qXfer:features:read` and
+//! `QStartNoAckMode`. Anything else gets the empty "unsupported" reply.
+//! Program output is sent as console output (`O`) packets before the next stop
+//! reply, and a client can interrupt `c` with Ctrl-C (byte 3). A program that
+//! reaches an input instruction with no input left stops with SIGTRAP, like a
+//! breakpoint, after a console message saying so.
+
+use super::{Int, IntcodeError, Program};
+use std::collections::{BTreeSet, VecDeque};
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+
+/// number of cells per byte address
+const CELL: u64 = 8;
+/// largest memory read or write, in bytes
+const MAX_TRANSFER: u64 = 2048;
+/// number of instructions between checks for an interrupt while continuing
+const POLL_STEPS: usize = 4096;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const TARGET_XML: &str = "<?xml version=\"1.0\"?>\
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+<target><feature name=\"org.intcode.core\">\
+<reg name=\"ip\" bitsize=\"64\" type=\"code_ptr\" regnum=\"0\"/>\
+<reg name=\"rel_base\" bitsize=\"64\" type=\"int64\" regnum=\"1\"/>\
+</feature></target>";
+
+/// A program being debugged by a remote client.
+pub struct GdbStub {
+    program: Program,
+    breakpoints: BTreeSet<Int>,
+    input: VecDeque<Int>,
+    output: Vec<Int>,
+    /// messages from the stub to send as console output
+    messages: Vec<String>,
+    ack: bool,
+}
+
+/// What the stub should do after replying to a packet.
+enum Next {
+    Continue,
+    Close,
+}
+
+impl GdbStub {
+    /// Debug program, with the values in input queued for its input
+    /// instructions.
+    pub fn new(program: Program, input: &[Int]) -> GdbStub {
+        GdbStub {
+            program,
+            breakpoints: BTreeSet::new(),
+            input: input.iter().copied().collect(),
+            output: Vec::new(),
+            messages: Vec::new(),
+            ack: true,
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Serve a single client until it kills or detaches from the program, or
+    /// disconnects.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = io::BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        while let Some(packet) = read_packet(&mut reader, &mut writer, self.ack)? {
+            let (reply, next) = match packet.as_str() {
+                "c" => (self.cont(&mut reader), Next::Continue),
+                _ => self.handle(&packet),
+            };
+            self.send_output(&mut writer)?;
+            write_packet(&mut writer, &reply)?;
+            if packet == "QStartNoAckMode" {
+                self.ack = false;
+            }
+            if let Next::Close = next {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reply to a packet other than continue.
+    fn handle(&mut self, packet: &str) -> (String, Next) {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => self.stop_reply(SIGTRAP),
+            Some(b'g') => {
+                encode_register((self.program.ip() as u64).wrapping_mul(CELL))
+                    + &encode_register(self.program.rel_base() as u64)
+            }
+            Some(b'G') => self.write_registers(&packet[1..]),
+            Some(b'p') => match u64::from_str_radix(&packet[1..], 16) {
+                Ok(0) => encode_register((self.program.ip() as u64).wrapping_mul(CELL)),
+                Ok(1) => encode_register(self.program.rel_base() as u64),
+                _ => "E00".to_string(),
+            },
+            Some(b'P') => self.write_register(&packet[1..]),
+            Some(b'm') => self.read_memory(&packet[1..]),
+            Some(b'M') => self.write_memory(&packet[1..]),
+            Some(b's') => self.step(),
+            Some(b'Z') | Some(b'z') => self.breakpoint(packet),
+            Some(b'k') => return (String::new(), Next::Close),
+            Some(b'D') => return ("OK".to_string(), Next::Close),
+            Some(b'H') => "OK".to_string(),
+            _ if packet.starts_with("qSupported") => {
+                "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string()
+            }
+            _ if packet == "qAttached" => "1".to_string(),
+            _ if packet == "QStartNoAckMode" => "OK".to_string(),
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => read_xfer(
+                TARGET_XML,
+                &packet["qXfer:features:read:target.xml:".len()..],
+            ),
+            _ => String::new(),
+        };
+        (reply, Next::Continue)
+    }
+
+    /// The reply for the program stopping with signal, or exiting if it has
+    /// halted.
+    fn stop_reply(&self, signal: u8) -> String {
+        if self.program.is_halted() {
+            "W00".to_string()
+        } else {
+            format!["S{:02x}", signal]
+        }
+    }
+
+    /// Execute one instruction, returning the signal to stop with if it
+    /// failed.
+    fn execute(&mut self) -> Result<(), u8> {
+        self.program
+            .step(None, &mut self.input, &mut self.output)
+            .map_err(|e| match e {
+                IntcodeError::InvalidOpcode { .. }
+                | IntcodeError::InvalidMode { .. }
+                | IntcodeError::WriteToImmediate { .. } => SIGILL,
+                IntcodeError::InputExhausted { .. } => {
+                    self.messages.push(e.to_string());
+                    SIGTRAP
+                }
+                _ => SIGSEGV,
+            })
+    }
+
+    fn step(&mut self) -> String {
+        match self.execute() {
+            Ok(()) => self.stop_reply(SIGTRAP),
+            Err(signal) => self.stop_reply(signal),
+        }
+    }
+
+    /// Run until a breakpoint, the program halts or fails, or the client
+    /// interrupts.
+    fn cont(&mut self, client: &mut io::BufReader<TcpStream>) -> String {
+        let mut steps = 0;
+        loop {
+            if self.program.is_halted() {
+                return self.stop_reply(SIGTRAP);
+            }
+            if let Err(signal) = self.execute() {
+                return self.stop_reply(signal);
+            }
+            if self.breakpoints.contains(&self.program.ip()) {
+                return self.stop_reply(SIGTRAP);
+            }
+            steps += 1;
+            if steps % POLL_STEPS == 0 && interrupted(client) {
+                return self.stop_reply(SIGINT);
+            }
+        }
+    }
+
+    /// Send output written by the program since the last call as console
+    /// output, one value per line, followed by any messages from the stub.
+    fn send_output<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let values = self.output.drain(..).map(|v| v.to_string());
+        for line in values.chain(self.messages.drain(..)) {
+            write_packet(
+                writer,
+                &format!["O{}", encode(format!["{}\n", line].as_bytes())],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        match (
+            data.get(..16).and_then(decode_register),
+            data.get(16..32).and_then(decode_register),
+        ) {
+            (Some(ip), Some(rb)) => {
+                self.program.set_ip((ip / CELL) as Int);
+                self.program.set_rel_base(rb as Int);
+                "OK".to_string()
+            }
+            _ => "E00".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, data: &str) -> String {
+        let (n, value) = match data.split_once('=') {
+            Some(nv) => nv,
+            None => return "E00".to_string(),
+        };
+        match (u64::from_str_radix(n, 16), decode_register(value)) {
+            (Ok(0), Some(ip)) => self.program.set_ip((ip / CELL) as Int),
+            (Ok(1), Some(rb)) => self.program.set_rel_base(rb as Int),
+            _ => return "E00".to_string(),
+        }
+        "OK".to_string()
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let (addr, len) = match parse_addr_len(args) {
+            Some(al) => al,
+            None => return "E00".to_string(),
+        };
+        let len = len.min(MAX_TRANSFER);
+        let mut bytes = Vec::with_capacity(len as usize);
+        for a in addr..addr + len {
+            match self.program.peek((a / CELL) as Int) {
+                Ok(v) => bytes.push(v.to_le_bytes()[(a % CELL) as usize]),
+                Err(_) => return "E01".to_string(),
+            }
+        }
+        encode(&bytes)
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let (addr, data) = match args.split_once(':').and_then(|(al, data)| {
+            let (addr, len) = parse_addr_len(al)?;
+            let data = decode(data)?;
+            if data.len() as u64 == len && len <= MAX_TRANSFER {
+                Some((addr, data))
+            } else {
+                None
+            }
+        }) {
+            Some(ad) => ad,
+            None => return "E00".to_string(),
+        };
+        for (a, byte) in (addr..).zip(data) {
+            let cell = (a / CELL) as Int;
+            let mut bytes = match self.program.peek(cell) {
+                Ok(v) => v.to_le_bytes(),
+                Err(_) => return "E01".to_string(),
+            };
+            bytes[(a % CELL) as usize] = byte;
+            if self.program.poke(cell, Int::from_le_bytes(bytes)).is_err() {
+                return "E01".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    /// Insert or remove a software breakpoint; other kinds are unsupported.
+    fn breakpoint(&mut self, packet: &str) -> String {
+        let mut fields = packet[1..].split(',');
+        let addr = match (fields.next(), fields.next()) {
+            (Some("0"), Some(addr)) => u64::from_str_radix(addr, 16),
+            _ => return String::new(),
+        };
+        match addr {
+            Ok(addr) => {
+                let cell = (addr / CELL) as Int;
+                if packet.starts_with('Z') {
+                    self.breakpoints.insert(cell);
+                } else {
+                    self.breakpoints.remove(&cell);
+                }
+                "OK".to_string()
+            }
+            Err(_) => "E00".to_string(),
+        }
+    }
+}
+
+/// Listen on addr, such as `127.0.0.1:1234`, and debug prog for the first
+/// client to connect.
+pub fn listen(prog: Program, input: &[Int], addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+    GdbStub::new(prog, input).serve(stream)
+}
+
+/// Read the next packet, acknowledging it if ack is set. Returns None when
+/// the client disconnects.
+fn read_packet<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    ack: bool,
+) -> io::Result<Option<String>> {
+    loop {
+        // skip acknowledgements and anything else outside a packet
+        let mut skipped = Vec::new();
+        if reader.read_until(b'$', &mut skipped)? == 0 || skipped.last() != Some(&b'$') {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        reader.read_until(b'#', &mut data)?;
+        if data.pop() != Some(b'#') {
+            return Ok(None);
+        }
+        let mut checksum = [0; 2];
+        reader.read_exact(&mut checksum)?;
+        let valid = std::str::from_utf8(&checksum)
+            .ok()
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            == Some(sum(&data));
+        if ack {
+            writer.write_all(if valid { b"+" } else { b"-" })?;
+        }
+        if valid {
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+}
+
+fn write_packet<W: Write>(writer: &mut W, data: &str) -> io::Result<()> {
+    write!(writer, "${}#{:02x}", data, sum(data.as_bytes()))?;
+    writer.flush()
+}
+
+/// Whether the client has sent an interrupt, without waiting for one.
+fn interrupted(client: &mut io::BufReader<TcpStream>) -> bool {
+    if client.buffer().contains(&3) {
+        client.consume(client.buffer().len());
+        return true;
+    }
+    let stream = client.get_ref();
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut byte = [0];
+    let got = matches!(stream.peek(&mut byte), Ok(1));
+    let _ = stream.set_nonblocking(false);
+    if got && byte[0] == 3 {
+        // consume the interrupt
+        let _ = client.read_exact(&mut byte);
+        return true;
+    }
+    false
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |s, b| s.wrapping_add(*b))
+}
+
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!["{:02x}", b]).collect()
+}
+
+/// Decode pairs of hex digits, failing if there is an odd digit left over.
+fn decode(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Registers are sent in target (little-endian) byte order.
+fn encode_register(value: u64) -> String {
+    encode(&value.to_le_bytes())
+}
+
+fn decode_register(hex: &str) -> Option<u64> {
+    let bytes = decode(hex)?;
+    let mut le = [0; 8];
+    if bytes.len() != le.len() {
+        return None;
+    }
+    le.copy_from_slice(&bytes);
+    Some(u64::from_le_bytes(le))
+}
+
+/// Parse `addr,length` in hex.
+fn parse_addr_len(s: &str) -> Option<(u64, u64)> {
+    let (addr, len) = s.split_once(',')?;
+    let addr = u64::from_str_radix(addr, 16).ok()?;
+    let len = u64::from_str_radix(len, 16).ok()?;
+    // the range must not wrap around; cell addresses (at most u64::MAX / 8)
+    // always fit in Int
+    addr.checked_add(len)?;
+    Some((addr, len))
+}
+
+/// Reply to a `qXfer` read of document at `offset,length`.
+fn read_xfer(document: &str, args: &str) -> String {
+    match parse_addr_len(args) {
+        Some((offset, len)) => {
+            let start = (offset as usize).min(document.len());
+            let end = (start + len as usize).min(document.len());
+            let more = if end < document.len() { "m" } else { "l" };
+            format!["{}{}", more, &document[start..end]]
+        }
+        None => "E00".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test_gdbstub {
+    use super::*;
+
+    #[test]
+    fn test_packet() {
+        let mut out = Vec::new();
+        let mut r = io::Cursor::new("+$g#67$m0,1#00");
+        assert_eq!(
+            read_packet(&mut r, &mut out, true).unwrap(),
+            Some("g".to_string())
+        );
+        assert_eq!(read_packet(&mut r, &mut out, true).unwrap(), None);
+        assert_eq!(out, b"+-");
+        out.clear();
+        write_packet(&mut out, "OK").unwrap();
+        assert_eq!(out, b"$OK#9a");
+    }
+
+    #[test]
+    fn test_registers() {
+        assert_eq!(encode_register(16), "1000000000000000");
+        assert_eq!(decode_register("1000000000000000"), Some(16));
+        assert_eq!(decode_register("10"), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("0a1b"), Some(vec![10, 27]));
+        assert_eq!(decode("0a1"), None);
+        assert_eq!(decode("0x"), None);
+    }
+}
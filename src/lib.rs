@@ -1,18 +1,39 @@
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::fmt;
 use std::io;
 use std::io::prelude::*;
 use std::str::FromStr;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+pub mod asm;
+pub mod compile;
 pub mod debugger;
+pub mod disassemble;
+mod error;
+pub mod gdbstub;
+mod loops;
+mod memory;
 pub mod permutations;
+pub mod ports;
+pub mod profile;
+pub mod trace;
+pub mod watch;
+
+pub use error::IntcodeError;
+use loops::LoopDetector;
+use memory::Memory;
+pub use ports::{Input, IntInput, IntOutput, Output};
+pub use profile::Profile;
+pub use trace::{TraceEvent, Tracer};
+pub use watch::{Access, WatchHit, Watchpoint};
 
 // the fundamental type of an Intcode program, used for both addresses and
 // values (since one can easily become the other)
 pub type Int = i64;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum Operation {
     End,
     Add,
@@ -27,6 +48,43 @@ enum Operation {
 }
 
 impl Operation {
+    const ALL: [Operation; 10] = [
+        Operation::End,
+        Operation::Add,
+        Operation::Mul,
+        Operation::Input,
+        Operation::Output,
+        Operation::JumpNotZero,
+        Operation::JumpZero,
+        Operation::LessThan,
+        Operation::EqualTo,
+        Operation::RelBase,
+    ];
+
+    /// Opcode of the operation, the inverse of `Instruction::op`.
+    pub fn code(&self) -> Int {
+        match self {
+            Operation::End => 99,
+            Operation::Add => 1,
+            Operation::Mul => 2,
+            Operation::Input => 3,
+            Operation::Output => 4,
+            Operation::JumpNotZero => 5,
+            Operation::JumpZero => 6,
+            Operation::LessThan => 7,
+            Operation::EqualTo => 8,
+            Operation::RelBase => 9,
+        }
+    }
+
+    /// Look up an operation by its mnemonic, as shown by `Display`.
+    pub fn from_mnemonic(s: &str) -> Option<Operation> {
+        Operation::ALL
+            .iter()
+            .find(|op| op.to_string().trim_end().eq_ignore_ascii_case(s))
+            .copied()
+    }
+
     pub fn len(&self) -> usize {
         match self {
             Operation::End => 1,
@@ -41,59 +99,72 @@ impl Operation {
             Operation::RelBase => 2,
         }
     }
+
+    /// Number (starting at 1) of the parameter the operation writes to, if
+    /// any.
+    pub fn writes(&self) -> Option<usize> {
+        match self {
+            Operation::Add | Operation::Mul | Operation::LessThan | Operation::EqualTo => Some(3),
+            Operation::Input => Some(1),
+            _ => None,
+        }
+    }
+}
+
+impl Operation {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Operation::End => "END",
+            Operation::Add => "ADD",
+            Operation::Mul => "MUL",
+            Operation::Input => "INP",
+            Operation::Output => "OUT",
+            Operation::JumpNotZero => "JNZ",
+            Operation::JumpZero => "JZ",
+            Operation::LessThan => "LT",
+            Operation::EqualTo => "EQ",
+            Operation::RelBase => "REL",
+        }
+    }
 }
 
 impl fmt::Display for Operation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{:3}",
-            match self {
-                Operation::End => "END",
-                Operation::Add => "ADD",
-                Operation::Mul => "MUL",
-                Operation::Input => "INP",
-                Operation::Output => "OUT",
-                Operation::JumpNotZero => "JNZ",
-                Operation::JumpZero => "JZ",
-                Operation::LessThan => "LT",
-                Operation::EqualTo => "EQ",
-                Operation::RelBase => "REL",
-            }
-        )
+        write!(f, "{:3}", self.mnemonic())
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum Mode {
+/// Parameter mode of an instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
     Pointer,
     Value,
     Relative,
 }
 
 trait Instruction {
-    fn op(&self) -> Operation;
-    fn modes(&self) -> Vec<Mode>;
+    fn op(&self) -> Option<Operation>;
+    fn modes(&self) -> Option<Vec<Mode>>;
 }
 
 impl Instruction for Int {
-    fn op(&self) -> Operation {
+    fn op(&self) -> Option<Operation> {
         match self % 100 {
-            99 => Operation::End,
-            1 => Operation::Add,
-            2 => Operation::Mul,
-            3 => Operation::Input,
-            4 => Operation::Output,
-            5 => Operation::JumpNotZero,
-            6 => Operation::JumpZero,
-            7 => Operation::LessThan,
-            8 => Operation::EqualTo,
-            9 => Operation::RelBase,
-            i => panic!["unknown Instruction {}", i],
+            99 => Some(Operation::End),
+            1 => Some(Operation::Add),
+            2 => Some(Operation::Mul),
+            3 => Some(Operation::Input),
+            4 => Some(Operation::Output),
+            5 => Some(Operation::JumpNotZero),
+            6 => Some(Operation::JumpZero),
+            7 => Some(Operation::LessThan),
+            8 => Some(Operation::EqualTo),
+            9 => Some(Operation::RelBase),
+            _ => None,
         }
     }
 
-    fn modes(&self) -> Vec<Mode> {
+    fn modes(&self) -> Option<Vec<Mode>> {
         let mut m: Vec<Mode> = Vec::new();
         let mut r = self / 100;
         for _ in 0..3 {
@@ -101,11 +172,11 @@ impl Instruction for Int {
                 0 => Mode::Pointer,
                 1 => Mode::Value,
                 2 => Mode::Relative,
-                _ => panic!["unknown mode {}", r],
+                _ => return None,
             });
             r /= 10;
         }
-        m
+        Some(m)
     }
 }
 
@@ -117,7 +188,7 @@ mod test_instruction {
     fn test_op_end() {
         let cells: Vec<Int> = vec![99, 1099, 11199];
         for c in cells {
-            assert_eq!(c.op(), Operation::End, "cell value: {}", c);
+            assert_eq!(c.op(), Some(Operation::End), "cell value: {}", c);
         }
     }
 
@@ -125,87 +196,250 @@ mod test_instruction {
     fn test_op_add() {
         let cells: Vec<Int> = vec![1, 101, 11101];
         for c in cells {
-            assert_eq!(c.op(), Operation::Add, "cell value: {}", c);
+            assert_eq!(c.op(), Some(Operation::Add), "cell value: {}", c);
         }
     }
 
     #[test]
-    #[should_panic]
     fn test_op_other() {
         let c: Int = 10;
-        c.op();
+        assert_eq!(c.op(), None);
     }
 
     #[test]
     fn test_modes_000() {
         let c: Int = 99;
-        assert_eq!(c.modes(), vec![Mode::Pointer, Mode::Pointer, Mode::Pointer])
+        assert_eq!(
+            c.modes(),
+            Some(vec![Mode::Pointer, Mode::Pointer, Mode::Pointer])
+        )
     }
 
     #[test]
     fn test_modes_001() {
         let c: Int = 199;
-        assert_eq!(c.modes(), vec![Mode::Value, Mode::Pointer, Mode::Pointer])
+        assert_eq!(
+            c.modes(),
+            Some(vec![Mode::Value, Mode::Pointer, Mode::Pointer])
+        )
     }
 
     #[test]
     fn test_modes_100() {
         let c: Int = 10001;
-        assert_eq!(c.modes(), vec![Mode::Pointer, Mode::Pointer, Mode::Value])
+        assert_eq!(
+            c.modes(),
+            Some(vec![Mode::Pointer, Mode::Pointer, Mode::Value])
+        )
     }
 
     #[test]
     fn test_modes_102() {
         let c: Int = 10209;
-        assert_eq!(c.modes(), vec![Mode::Relative, Mode::Pointer, Mode::Value])
+        assert_eq!(
+            c.modes(),
+            Some(vec![Mode::Relative, Mode::Pointer, Mode::Value])
+        )
     }
 
     #[test]
-    #[should_panic]
     fn test_modes_other() {
         let c: Int = 399;
-        c.modes();
+        assert_eq!(c.modes(), None);
     }
 }
 
-pub enum Input<'a> {
-    None,
-    String(&'a str),
-    Reader(&'a mut dyn BufRead),
-    Channel(Receiver<Int>),
+/// Reason `Program::run_until_io` or `Program::exe` stopped executing.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RunState {
+    /// the program reached an END instruction
+    Halted,
+    /// the program is at an input instruction and no input is queued
+    NeedsInput,
+    /// the program output a value
+    Output(Int),
+    /// the last instruction accessed a watched address; this is the first
+    /// access, all of them are available from `Program::watch_hits`
+    Watchpoint(WatchHit),
+    /// `exe` executed `Options::fuel` instructions without halting
+    BudgetExhausted,
+    /// the `Options::cancel` handle was cancelled
+    Cancelled,
 }
 
-pub enum Output<'a> {
-    None,
-    Writer(&'a mut dyn Write),
-    Channel(Sender<Int>),
+/// How arithmetic that overflows `Int` is handled: sums and products
+/// computed by instructions, and relative base updates. Addresses that
+/// overflow, of parameters and of the next instruction, are always an error.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Overflow {
+    /// fail with `IntcodeError::Overflow`
+    #[default]
+    Error,
+    /// wrap around at the bounds of `Int`
+    Wrapping,
+    /// clamp to `Int::MIN` or `Int::MAX`
+    Saturating,
 }
 
-#[derive(Clone)]
+/// Arithmetic operation, as reported by `IntcodeError::Overflow`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Mul,
+}
+
+impl fmt::Display for ArithOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithOp::Add => write!(f, "+"),
+            ArithOp::Mul => write!(f, "*"),
+        }
+    }
+}
+
+/// Handle for stopping `Program::exe` from another thread. Clones share the
+/// same flag, so one handle can stop several programs.
+#[derive(Clone, Debug, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn new() -> Cancel {
+        Cancel::default()
+    }
+
+    /// Make programs using this handle stop before their next instruction.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Undo `cancel`, so the programs can be resumed.
+    pub fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Record of an executed instruction, with what is needed to undo it.
+#[derive(Clone, Debug, PartialEq)]
 pub struct StackEntry {
     address: Int,
     value: Int,
+    rel_base: Int,
+    /// address written and the value it held before, if it was allocated
+    write: Option<(Int, Option<Int>)>,
+    input: Option<Int>,
+    output: Option<Int>,
+}
+
+impl StackEntry {
+    /// Address of the instruction.
+    pub fn address(&self) -> Int {
+        self.address
+    }
+
+    pub fn instruction(&self) -> Int {
+        self.value
+    }
+
+    /// Input consumed by the instruction.
+    pub fn input(&self) -> Option<Int> {
+        self.input
+    }
+
+    /// Value output by the instruction.
+    pub fn output(&self) -> Option<Int> {
+        self.output
+    }
+}
+
+/// Options controlling how a program is executed.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// number of executed instructions kept for `Program::step_back`; zero
+    /// (the default) disables recording history
+    pub history: usize,
+    /// maximum number of memory cells the program may allocate; writes
+    /// beyond the limit fail with `IntcodeError::MemoryLimit`
+    pub memory_limit: Option<usize>,
+    /// count the instructions executed, available from `Program::profile`
+    pub profile: bool,
+    /// decode every instruction as it is executed instead of caching decoded
+    /// instructions; only for comparison in the benchmark
+    #[cfg(feature = "uncached")]
+    pub uncached: bool,
+    /// maximum number of instructions executed by one call to
+    /// `Program::exe`, after which it returns `RunState::BudgetExhausted`
+    pub fuel: Option<usize>,
+    /// handle checked before each instruction executed by `Program::exe`,
+    /// which returns `RunState::Cancelled` once it's cancelled
+    pub cancel: Option<Cancel>,
+    /// fail with `IntcodeError::InfiniteLoop` when a backward jump returns
+    /// to an earlier state with no input or output in between
+    pub detect_loops: bool,
+    /// what happens when arithmetic overflows
+    pub overflow: Overflow,
+}
+
+/// An instruction decoded once and cached by address.
+#[derive(Clone, Copy, Debug)]
+struct Decoded {
+    value: Int,
+    op: Operation,
+    modes: [Mode; 3],
 }
 
 #[derive(Clone)]
 pub struct Program {
     source: Vec<Int>,
-    mem: Vec<Int>,
+    mem: Memory,
     rel_base: Int,
-    stack: Vec<StackEntry>,
+    stack: VecDeque<StackEntry>,
+    options: Options,
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,
+    ip: Int,
+    halted: bool,
+    steps: usize,
+    inbox: VecDeque<Int>,
+    /// output held back by `run_until_io` to report a watchpoint first
+    pending_output: Option<Int>,
+    profile: Option<Profile>,
+    /// decoded instructions in the program image, cleared by writes
+    cache: Vec<Option<Decoded>>,
+    last_write: Option<Int>,
+    loops: Option<LoopDetector>,
 }
 
 impl Program {
     pub fn new<R: BufRead>(reader: R) -> Program {
-        let c = match Program::read_code(reader) {
-            Ok(x) => x,
+        match Program::read_code(reader) {
+            Ok(c) => Program::from_code(c),
             Err(error) => panic!["{:}", error],
-        };
+        }
+    }
+
+    /// Create a program from already parsed code, e.g. from the assembler.
+    pub fn from_code(c: Vec<Int>) -> Program {
         Program {
-            source: c.clone(),
-            mem: c,
+            mem: Memory::new(c.clone()),
             rel_base: 0,
-            stack: Vec::new(),
+            stack: VecDeque::new(),
+            options: Options::default(),
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            ip: 0,
+            halted: false,
+            steps: 0,
+            inbox: VecDeque::new(),
+            pending_output: None,
+            profile: None,
+            cache: vec![None; c.len()],
+            last_write: None,
+            loops: None,
+            source: c,
         }
     }
 
@@ -214,7 +448,7 @@ impl Program {
         let mut c: Vec<Int> = Vec::new();
         for l in reader.lines() {
             for s in l?.split(',') {
-                if s.len() > 0 {
+                if !s.is_empty() {
                     match Int::from_str(s) {
                         Ok(n) => c.push(n),
                         Err(error) => {
@@ -227,200 +461,629 @@ impl Program {
         Ok(c)
     }
 
-    /// Reset program memory to source.
+    /// Reset program memory to source and rewind to the first instruction.
     pub fn reset(&mut self) {
-        self.mem = self.source.clone();
+        self.mem.load(&self.source);
+        self.cache = vec![None; self.source.len()];
+        self.rel_base = 0;
+        self.stack.clear();
+        self.watch_hits.clear();
+        self.last_write = None;
+        self.ip = 0;
+        self.halted = false;
+        self.steps = 0;
+        self.inbox.clear();
+        self.pending_output = None;
+        if self.profile.is_some() {
+            self.profile = Some(Profile::new(self.mem.allocated()));
+        }
+        if self.loops.is_some() {
+            self.loops = Some(LoopDetector::new(&self.mem));
+        }
     }
 
-    pub fn peek(&self, addr: Int) -> Int {
-        let addr: usize = addr.try_into().unwrap();
-        *self.mem.get(addr).unwrap()
+    /// Read the value at addr. Memory that has never been written reads as
+    /// zero.
+    pub fn peek(&self, addr: Int) -> Result<Int, IntcodeError> {
+        let addr: usize = addr
+            .try_into()
+            .map_err(|_| IntcodeError::NegativeAddress { address: addr })?;
+        Ok(self.mem.read(addr))
     }
 
-    pub fn poke(&mut self, addr: Int, value: Int) -> Option<Int> {
-        let addr: usize = addr.try_into().unwrap();
-        let mut old: Option<Int> = None;
-        if addr > self.mem.len() - 1 {
-            self.mem.resize_with(addr + 1, Default::default);
-        } else {
-            old = Some(self.mem[addr]);
+    /// Write value to addr and return the previous value if the address was
+    /// already allocated.
+    pub fn poke(&mut self, addr: Int, value: Int) -> Result<Option<Int>, IntcodeError> {
+        let old = self.write(addr, value)?;
+        self.forget_states();
+        Ok(old)
+    }
+
+    /// Write value to addr like `poke`, but as part of running the program,
+    /// so the states seen by loop detection stay valid.
+    fn write(&mut self, addr: Int, value: Int) -> Result<Option<Int>, IntcodeError> {
+        let addr: usize = addr
+            .try_into()
+            .map_err(|_| IntcodeError::NegativeAddress { address: addr })?;
+        let old = self.mem.write(addr, value)?;
+        if let Some(decoded) = self.cache.get_mut(addr) {
+            *decoded = None;
+        }
+        if let Some(loops) = self.loops.as_mut() {
+            loops.write(addr, old.unwrap_or_default(), value);
         }
-        self.mem[addr] = value;
-        old
+        Ok(old)
     }
 
-    fn pval(&self, mode: &Mode, addr: Int) -> Int {
-        match mode {
-            Mode::Pointer => self.peek(addr),
-            Mode::Value => addr,
-            Mode::Relative => self.peek(addr + self.rel_base),
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: Options) {
+        self.mem.set_limit(options.memory_limit);
+        while self.stack.len() > options.history {
+            self.stack.pop_front();
+        }
+        if !options.profile {
+            self.profile = None;
+        } else if self.profile.is_none() {
+            self.profile = Some(Profile::new(self.mem.allocated()));
+        }
+        if !options.detect_loops {
+            self.loops = None;
+        } else if self.loops.is_none() {
+            self.loops = Some(LoopDetector::new(&self.mem));
+        }
+        self.options = options;
+    }
+
+    /// Compute lhs op rhs according to `Options::overflow`, on behalf of the
+    /// instruction at address.
+    fn arith(&self, address: Int, lhs: Int, op: ArithOp, rhs: Int) -> Result<Int, IntcodeError> {
+        let (checked, wrapping, saturating) = match op {
+            ArithOp::Add => (
+                lhs.checked_add(rhs),
+                lhs.wrapping_add(rhs),
+                lhs.saturating_add(rhs),
+            ),
+            ArithOp::Mul => (
+                lhs.checked_mul(rhs),
+                lhs.wrapping_mul(rhs),
+                lhs.saturating_mul(rhs),
+            ),
+        };
+        match self.options.overflow {
+            Overflow::Error => checked.ok_or_else(|| IntcodeError::Overflow {
+                address,
+                instruction: self.peek(address).unwrap_or_default(),
+                lhs,
+                op,
+                rhs,
+            }),
+            Overflow::Wrapping => Ok(wrapping),
+            Overflow::Saturating => Ok(saturating),
         }
     }
 
-    fn paddr(&self, mode: &Mode, addr: Int) -> Int {
+    /// Compute the address base + n on behalf of the instruction at address.
+    /// An address that overflows fails with `IntcodeError::Overflow` whatever
+    /// `Options::overflow` says, as there is no memory beyond it to access.
+    fn offset(&self, address: Int, base: Int, n: Int) -> Result<Int, IntcodeError> {
+        base.checked_add(n).ok_or_else(|| IntcodeError::Overflow {
+            address,
+            instruction: self.peek(address).unwrap_or_default(),
+            lhs: base,
+            op: ArithOp::Add,
+            rhs: n,
+        })
+    }
+
+    fn pval(&self, mode: &Mode, addr: Int) -> Result<Int, IntcodeError> {
         match mode {
-            Mode::Pointer => addr,
-            Mode::Value => panic!["Value mode not valid for address"],
-            Mode::Relative => addr + self.rel_base,
+            Mode::Pointer => self.peek(addr),
+            Mode::Value => Ok(addr),
+            Mode::Relative => self.peek(self.offset(self.ip, addr, self.rel_base)?),
         }
     }
 
+    /// Resolve the address a parameter refers to, or None for immediate mode.
+    fn paddr(&self, mode: &Mode, addr: Int) -> Result<Option<Int>, IntcodeError> {
+        Ok(match mode {
+            Mode::Pointer => Some(addr),
+            Mode::Value => None,
+            Mode::Relative => Some(self.offset(self.ip, addr, self.rel_base)?),
+        })
+    }
+
+    /// Value of parameter n (starting at 1) of the instruction at addr.
+    fn arg(&self, modes: &[Mode], addr: Int, n: usize) -> Result<Int, IntcodeError> {
+        let p = self.peek(self.offset(addr, addr, n as Int)?)?;
+        self.pval(&modes[n - 1], p)
+    }
+
+    /// Address written by parameter n (starting at 1) of the instruction at addr.
+    fn dest(&self, modes: &[Mode], addr: Int, n: usize) -> Result<Int, IntcodeError> {
+        let p = self.peek(self.offset(addr, addr, n as Int)?)?;
+        self.paddr(&modes[n - 1], p)?
+            .ok_or(IntcodeError::WriteToImmediate {
+                address: addr,
+                instruction: self.peek(addr)?,
+            })
+    }
+
     pub fn rel_base(&self) -> Int {
         self.rel_base
     }
 
-    pub fn exe(
+    /// Address of the next instruction to execute.
+    pub fn ip(&self) -> Int {
+        self.ip
+    }
+
+    /// Move the instruction pointer. A halted program resumes from there.
+    pub fn set_ip(&mut self, ip: Int) {
+        self.ip = ip;
+        self.halted = false;
+        self.forget_states();
+    }
+
+    pub fn set_rel_base(&mut self, rel_base: Int) {
+        self.rel_base = rel_base;
+        self.forget_states();
+    }
+
+    /// Forget the states seen by loop detection, after the state was changed
+    /// from outside the program, so they can't be mistaken for a loop.
+    fn forget_states(&mut self) {
+        if let Some(loops) = self.loops.as_mut() {
+            loops.forget();
+        }
+    }
+
+    /// Whether the program has executed an END instruction.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Profile of the instructions executed since profiling was enabled or
+    /// the program was reset, if `Options::profile` is set.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Number of instructions executed since the program was loaded or reset.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Run from the current instruction pointer until the program halts,
+    /// accesses a watched address, runs out of `Options::fuel` or is
+    /// cancelled. Calling this again resumes where it stopped.
+    pub fn exe<I: IntInput, O: IntOutput>(
+        &mut self,
+        mut tracer: Option<&mut (dyn Tracer + '_)>,
+        mut input: I,
+        mut output: O,
+    ) -> Result<RunState, IntcodeError> {
+        let mut executed = 0;
+        while !self.halted {
+            if self
+                .options
+                .cancel
+                .as_ref()
+                .is_some_and(Cancel::is_cancelled)
+            {
+                return Ok(RunState::Cancelled);
+            }
+            if self.options.fuel == Some(executed) {
+                return Ok(RunState::BudgetExhausted);
+            }
+            self.step(tracer.as_deref_mut(), &mut input, &mut output)?;
+            executed += 1;
+            if let Some(hit) = self.watch_hits.first() {
+                return Ok(RunState::Watchpoint(*hit));
+            }
+        }
+        Ok(RunState::Halted)
+    }
+
+    /// Watch the addresses start to end inclusive, so that execution pauses
+    /// after an instruction accesses them.
+    pub fn watch(&mut self, start: Int, end: Int, access: Access) {
+        self.watchpoints.push(Watchpoint { start, end, access });
+    }
+
+    /// Remove watchpoints on exactly the addresses start to end, returning
+    /// whether there were any.
+    pub fn unwatch(&mut self, start: Int, end: Int) -> bool {
+        let n = self.watchpoints.len();
+        self.watchpoints
+            .retain(|w| w.start != start || w.end != end);
+        self.watchpoints.len() != n
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Watched accesses made by the last executed instruction.
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+
+    /// Address written by the last executed instruction, if it wrote one.
+    pub fn last_write(&self) -> Option<Int> {
+        self.last_write
+    }
+
+    /// Queue a value to be consumed by the next input instruction reached by
+    /// `run_until_io`.
+    pub fn push_input(&mut self, value: Int) {
+        self.inbox.push_back(value);
+    }
+
+    /// Run from the current instruction pointer until the program halts,
+    /// produces an output, or needs input that has not been queued with
+    /// `push_input`. The instruction pointer is kept in the program, so
+    /// calling this again resumes where it stopped. An output instruction
+    /// that also hits a watchpoint returns `RunState::Watchpoint` first and
+    /// its `RunState::Output` on the next call.
+    pub fn run_until_io(
+        &mut self,
+        mut tracer: Option<&mut (dyn Tracer + '_)>,
+    ) -> Result<RunState, IntcodeError> {
+        if let Some(o) = self.pending_output.take() {
+            return Ok(RunState::Output(o));
+        }
+        while !self.halted {
+            let (v, op, modes) = self.fetch(self.ip)?;
+            let i = match op {
+                Operation::Input => match self.inbox.pop_front() {
+                    Some(i) => Some(i),
+                    None => return Ok(RunState::NeedsInput),
+                },
+                _ => None,
+            };
+            let o = self.execute(v, op, &modes, tracer.as_deref_mut(), i)?;
+            if let Some(hit) = self.watch_hits.first() {
+                self.pending_output = o;
+                return Ok(RunState::Watchpoint(*hit));
+            }
+            if let Some(o) = o {
+                return Ok(RunState::Output(o));
+            }
+        }
+        Ok(RunState::Halted)
+    }
+
+    /// Execute the instruction at the instruction pointer. Does nothing if the
+    /// program has halted.
+    pub fn step<I: IntInput + ?Sized, O: IntOutput + ?Sized>(
         &mut self,
-        addr: Int,
-        trace: bool,
-        mut input: Input,
-        mut output: Output,
-    ) -> io::Result<()> {
-        let mut addr = addr;
-        loop {
-            match self.step(addr, trace, &mut input, &mut output) {
-                Ok(-1) => break,
-                Ok(r) => addr = r,
-                Err(e) => return Err(e),
+        tracer: Option<&mut (dyn Tracer + '_)>,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<(), IntcodeError> {
+        if self.halted {
+            return Ok(());
+        }
+        let addr = self.ip;
+        let (v, op, modes) = self.fetch(addr)?;
+        let i = match op {
+            Operation::Input => {
+                if input.interactive() {
+                    output.prompt().map_err(|e| e.at(addr, v))?;
+                }
+                Some(input.read().map_err(|e| e.at(addr, v))?)
             }
+            _ => None,
+        };
+        if let Some(o) = self.execute(v, op, &modes, tracer, i)? {
+            output.write(o).map_err(|e| e.at(addr, v))?;
         }
         Ok(())
     }
 
-    pub fn step(
+    /// Decode the instruction at addr, from the cache if it's in the program
+    /// image and caching isn't disabled.
+    fn fetch(&mut self, addr: Int) -> Result<(Int, Operation, [Mode; 3]), IntcodeError> {
+        #[cfg(feature = "uncached")]
+        {
+            if self.options.uncached {
+                return self.decode(addr);
+            }
+        }
+        let index: usize = match addr.try_into() {
+            Ok(i) if i < self.cache.len() => i,
+            _ => return self.decode(addr),
+        };
+        if let Some(d) = self.cache[index] {
+            return Ok((d.value, d.op, d.modes));
+        }
+        let (value, op, modes) = self.decode(addr)?;
+        self.cache[index] = Some(Decoded { value, op, modes });
+        Ok((value, op, modes))
+    }
+
+    /// Fetch and decode the instruction at addr.
+    fn decode(&self, addr: Int) -> Result<(Int, Operation, [Mode; 3]), IntcodeError> {
+        let v = self.peek(addr)?;
+        let op = v.op().ok_or(IntcodeError::InvalidOpcode {
+            address: addr,
+            instruction: v,
+        })?;
+        let modes = v.modes().ok_or(IntcodeError::InvalidMode {
+            address: addr,
+            instruction: v,
+        })?;
+        Ok((v, op, [modes[0], modes[1], modes[2]]))
+    }
+
+    /// Execute the decoded instruction v at the instruction pointer, advance
+    /// to the next instruction and return the value output, if any. Input
+    /// instructions consume input, which must have already been read by the
+    /// caller.
+    fn execute(
         &mut self,
-        addr: Int,
-        trace: bool,
-        input: &mut Input,
-        output: &mut Output,
-    ) -> io::Result<Int> {
-        let mut addr = addr;
-        let v = self.peek(addr);
-        let op = v.op();
-        let modes = v.modes();
-        self.stack.push(StackEntry {
+        v: Int,
+        op: Operation,
+        modes: &[Mode],
+        tracer: Option<&mut (dyn Tracer + '_)>,
+        input: Option<Int>,
+    ) -> Result<Option<Int>, IntcodeError> {
+        let mut addr = self.ip;
+        let mut jumped = false;
+        let mut entry = StackEntry {
             address: addr,
             value: v,
-        });
-        if trace {
-            eprintln!["{}: {} ({:?})", addr, v, op];
+            rel_base: self.rel_base,
+            write: None,
+            input,
+            output: None,
+        };
+        self.watch_hits.clear();
+        if !self.watchpoints.is_empty() {
+            self.watch_reads(op, modes)?;
         }
+        // resolve parameters before the instruction can overwrite them
+        let params = if tracer.is_some() {
+            self.trace_params(op, modes)
+        } else {
+            Vec::new()
+        };
         match op {
-            Operation::End => return Ok(-1),
+            Operation::End => self.halted = true,
             Operation::Add => {
-                self.poke(
-                    self.paddr(&modes[2], self.peek(addr + 3)),
-                    self.pval(&modes[0], self.peek(addr + 1))
-                        + self.pval(&modes[1], self.peek(addr + 2)),
-                );
-                addr += 4;
+                self.store(
+                    &mut entry,
+                    self.dest(modes, addr, 3)?,
+                    self.arith(
+                        addr,
+                        self.arg(modes, addr, 1)?,
+                        ArithOp::Add,
+                        self.arg(modes, addr, 2)?,
+                    )?,
+                )?;
+                addr = self.offset(addr, addr, 4)?;
             }
             Operation::Mul => {
-                self.poke(
-                    self.paddr(&modes[2], self.peek(addr + 3)),
-                    self.pval(&modes[0], self.peek(addr + 1))
-                        * self.pval(&modes[1], self.peek(addr + 2)),
-                );
-                addr += 4;
+                self.store(
+                    &mut entry,
+                    self.dest(modes, addr, 3)?,
+                    self.arith(
+                        addr,
+                        self.arg(modes, addr, 1)?,
+                        ArithOp::Mul,
+                        self.arg(modes, addr, 2)?,
+                    )?,
+                )?;
+                addr = self.offset(addr, addr, 4)?;
             }
             Operation::Input => {
-                let i = match input {
-                    Input::String(s) => match Int::from_str(&s.trim()) {
-                        Ok(n) => n,
-                        Err(error) => {
-                            return Err(io::Error::new(io::ErrorKind::InvalidData, error))
-                        }
-                    },
-                    Input::Reader(ref mut r) => {
-                        if let Output::Writer(ref mut w) = output {
-                            w.write_all(b"?")?;
-                            w.flush()?;
-                        }
-                        let mut s = String::new();
-                        r.read_line(&mut s)?;
-                        match Int::from_str(&s.trim()) {
-                            Ok(n) => n,
-                            Err(error) => {
-                                return Err(io::Error::new(io::ErrorKind::InvalidData, error))
-                            }
-                        }
-                    }
-                    Input::Channel(ref c) => c.recv().unwrap(),
-                    Input::None => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "input required but no input channel provided",
-                        ));
-                    }
-                };
-                if trace {
-                    eprintln!["input data: \"{}\"", i];
-                }
-                self.poke(self.paddr(&modes[0], self.peek(addr + 1)), i);
-                addr += 2;
+                let i = input.ok_or(IntcodeError::InputExhausted {
+                    address: addr,
+                    instruction: v,
+                })?;
+                self.store(&mut entry, self.dest(modes, addr, 1)?, i)?;
+                addr = self.offset(addr, addr, 2)?;
             }
             Operation::Output => {
-                let o = self.pval(&modes[0], self.peek(addr + 1));
-                if trace {
-                    eprintln!["output data: \"{}\"", o];
-                }
-                match output {
-                    Output::Writer(ref mut w) => {
-                        writeln!(w, "{}", o)?;
-                    }
-                    Output::Channel(ref c) => {
-                        c.send(o).unwrap();
-                    }
-                    Output::None => {}
-                }
-                addr += 2;
+                let o = self.arg(modes, addr, 1)?;
+                entry.output = Some(o);
+                addr = self.offset(addr, addr, 2)?;
             }
             Operation::JumpNotZero => {
-                if self.pval(&modes[0], self.peek(addr + 1)) != 0 {
-                    addr = self.pval(&modes[1], self.peek(addr + 2));
+                if self.arg(modes, addr, 1)? != 0 {
+                    addr = self.arg(modes, addr, 2)?;
+                    jumped = true;
                 } else {
-                    addr += 3;
+                    addr = self.offset(addr, addr, 3)?;
                 }
             }
             Operation::JumpZero => {
-                if self.pval(&modes[0], self.peek(addr + 1)) == 0 {
-                    addr = self.pval(&modes[1], self.peek(addr + 2));
+                if self.arg(modes, addr, 1)? == 0 {
+                    addr = self.arg(modes, addr, 2)?;
+                    jumped = true;
                 } else {
-                    addr += 3;
+                    addr = self.offset(addr, addr, 3)?;
                 }
             }
             Operation::LessThan => {
-                self.poke(
-                    self.paddr(&modes[2], self.peek(addr + 3)),
-                    if self.pval(&modes[0], self.peek(addr + 1))
-                        < self.pval(&modes[1], self.peek(addr + 2))
-                    {
+                self.store(
+                    &mut entry,
+                    self.dest(modes, addr, 3)?,
+                    if self.arg(modes, addr, 1)? < self.arg(modes, addr, 2)? {
                         1
                     } else {
                         0
                     },
-                );
-                addr += 4;
+                )?;
+                addr = self.offset(addr, addr, 4)?;
             }
             Operation::EqualTo => {
-                self.poke(
-                    self.paddr(&modes[2], self.peek(addr + 3)),
-                    if self.pval(&modes[0], self.peek(addr + 1))
-                        == self.pval(&modes[1], self.peek(addr + 2))
-                    {
+                self.store(
+                    &mut entry,
+                    self.dest(modes, addr, 3)?,
+                    if self.arg(modes, addr, 1)? == self.arg(modes, addr, 2)? {
                         1
                     } else {
                         0
                     },
-                );
-                addr += 4;
+                )?;
+                addr = self.offset(addr, addr, 4)?;
             }
             Operation::RelBase => {
-                self.rel_base += self.pval(&modes[0], self.peek(addr + 1));
-                addr += 2;
+                self.rel_base =
+                    self.arith(addr, self.rel_base, ArithOp::Add, self.arg(modes, addr, 1)?)?;
+                addr = self.offset(addr, addr, 2)?;
+            }
+        }
+        if let Some(loops) = self.loops.as_mut() {
+            loops.executed(entry.address);
+            if op == Operation::Input || op == Operation::Output {
+                loops.forget();
+            } else if (op == Operation::JumpNotZero || op == Operation::JumpZero)
+                && addr <= entry.address
+            {
+                if let Some((start, end)) = loops.jump(addr, self.rel_base, &self.mem) {
+                    return Err(IntcodeError::InfiniteLoop {
+                        address: entry.address,
+                        instruction: v,
+                        start,
+                        end,
+                    });
+                }
+            }
+        }
+        if let Some(tracer) = tracer {
+            let write = match entry.write {
+                Some((a, _)) => Some((a, self.peek(a)?)),
+                None => None,
+            };
+            tracer
+                .trace(&TraceEvent {
+                    step: self.steps,
+                    address: entry.address,
+                    instruction: v,
+                    mnemonic: op.mnemonic(),
+                    rel_base: entry.rel_base,
+                    params,
+                    write,
+                    input,
+                    output: entry.output,
+                    jumped,
+                    next: addr,
+                })
+                .map_err(IntcodeError::Io)?;
+        }
+        self.ip = addr;
+        self.steps += 1;
+        self.last_write = entry.write.map(|(a, _)| a);
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(entry.address, op, addr, self.mem.allocated());
+        }
+        let output = entry.output;
+        if self.options.history > 0 {
+            if self.stack.len() == self.options.history {
+                self.stack.pop_front();
+            }
+            self.stack.push_back(entry);
+        }
+        Ok(output)
+    }
+
+    /// Resolve the parameters of the instruction at the instruction pointer
+    /// for tracing.
+    fn trace_params(&self, op: Operation, modes: &[Mode]) -> Vec<trace::Param> {
+        (1..op.len())
+            .map(|n| {
+                let mode = modes[n - 1];
+                let raw = self
+                    .offset(self.ip, self.ip, n as Int)
+                    .and_then(|a| self.peek(a))
+                    .unwrap_or_default();
+                trace::Param {
+                    mode,
+                    raw,
+                    address: self.paddr(&mode, raw).ok().flatten(),
+                    value: if op.writes() == Some(n) {
+                        None
+                    } else {
+                        self.pval(&mode, raw).ok()
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Write value to addr, recording the previous value in entry so it can
+    /// be undone.
+    fn store(&mut self, entry: &mut StackEntry, addr: Int, value: Int) -> Result<(), IntcodeError> {
+        let old = self.write(addr, value)?;
+        entry.write = Some((addr, old));
+        if self
+            .watchpoints
+            .iter()
+            .any(|w| w.triggers(addr, Access::Write))
+        {
+            self.watch_hits.push(WatchHit {
+                address: entry.address,
+                target: addr,
+                old: old.unwrap_or_default(),
+                new: value,
+                access: Access::Write,
+            });
+        }
+        Ok(())
+    }
+
+    /// Record reads of watched addresses by the parameters of the instruction
+    /// at the instruction pointer.
+    fn watch_reads(&mut self, op: Operation, modes: &[Mode]) -> Result<(), IntcodeError> {
+        for n in 1..op.len() {
+            if op.writes() == Some(n) {
+                continue;
+            }
+            let raw = self.peek(self.offset(self.ip, self.ip, n as Int)?)?;
+            if let Some(target) = self.paddr(&modes[n - 1], raw)? {
+                if self
+                    .watchpoints
+                    .iter()
+                    .any(|w| w.triggers(target, Access::Read))
+                {
+                    let value = self.peek(target)?;
+                    self.watch_hits.push(WatchHit {
+                        address: self.ip,
+                        target,
+                        old: value,
+                        new: value,
+                        access: Access::Read,
+                    });
+                }
             }
         }
-        Ok(addr)
+        Ok(())
+    }
+
+    /// Undo the most recently executed instruction, restoring the memory it
+    /// wrote, the relative base and the instruction pointer. Returns the
+    /// undone entry, which holds any input the instruction consumed (it is
+    /// not returned to the input source) or output it produced, or None if
+    /// there is no more history. History is only recorded if enabled with
+    /// `Options::history`.
+    pub fn step_back(&mut self) -> Option<StackEntry> {
+        let entry = self.stack.pop_back()?;
+        self.pending_output = None;
+        if let Some((addr, old)) = entry.write {
+            // restoring a cell that was already written can't fail
+            self.write(addr, old.unwrap_or_default()).ok();
+        }
+        self.rel_base = entry.rel_base;
+        self.ip = entry.address;
+        self.halted = false;
+        self.steps -= 1;
+        self.forget_states();
+        Some(entry)
     }
 }
 
@@ -468,54 +1131,66 @@ mod test_intcode {
         let ic = Program::new(code);
 
         let cv = vec![1, 0, 0, 3, 1, 1];
-        assert_eq!(ic.mem, cv);
+        assert_eq!(ic.mem.dense(), cv.as_slice());
     }
 
     #[test]
     fn test_peek() {
         let code = io::Cursor::new("1,0,0,3,1,1");
         let ic = Program::new(code);
-        assert_eq!(ic.peek(3), 3);
+        assert_eq!(ic.peek(3).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_peek_unallocated() {
+        let code = io::Cursor::new("1,0,0,3,1,1");
+        let ic = Program::new(code);
+        assert_eq!(ic.peek(1000).unwrap(), 0);
+        assert!(matches!(
+            ic.peek(-1),
+            Err(IntcodeError::NegativeAddress { address: -1 })
+        ));
     }
 
     #[test]
     fn test_poke() {
         let code = io::Cursor::new("1,0,0,3,1,1");
         let mut ic = Program::new(code);
-        assert_eq!(ic.peek(3), 3);
-        assert_eq!(ic.poke(3, 5).unwrap(), 3);
-        assert_eq!(ic.peek(3), 5);
+        assert_eq!(ic.peek(3).unwrap(), 3);
+        assert_eq!(ic.poke(3, 5).unwrap(), Some(3));
+        assert_eq!(ic.peek(3).unwrap(), 5);
     }
 
     #[test]
     fn test_reset() {
         let code = io::Cursor::new("1,0,0,3,1,1");
         let mut ic = Program::new(code);
-        assert_eq!(ic.peek(3), 3);
-        assert_eq!(ic.poke(3, 5).unwrap(), 3);
-        assert_eq!(ic.peek(3), 5);
+        assert_eq!(ic.peek(3).unwrap(), 3);
+        assert_eq!(ic.poke(3, 5).unwrap(), Some(3));
+        assert_eq!(ic.peek(3).unwrap(), 5);
         ic.reset();
-        assert_eq!(ic.peek(3), 3);
+        assert_eq!(ic.peek(3).unwrap(), 3);
     }
 
     #[test]
     fn test_pval() {
         let code = io::Cursor::new("1,0,0,3,1,1");
         let mut ic = Program::new(code);
-        assert_eq!(ic.pval(&Mode::Pointer, 4), 1);
-        assert_eq!(ic.pval(&Mode::Value, 4), 4);
-        assert_eq!(ic.pval(&Mode::Relative, 3), 3);
+        assert_eq!(ic.pval(&Mode::Pointer, 4).unwrap(), 1);
+        assert_eq!(ic.pval(&Mode::Value, 4).unwrap(), 4);
+        assert_eq!(ic.pval(&Mode::Relative, 3).unwrap(), 3);
         ic.rel_base = 1;
-        assert_eq!(ic.pval(&Mode::Relative, 3), 1);
+        assert_eq!(ic.pval(&Mode::Relative, 3).unwrap(), 1);
     }
 
     #[test]
     fn test_paddr() {
         let code = io::Cursor::new("1,0,0,3,1,1");
         let mut ic = Program::new(code);
-        assert_eq!(ic.paddr(&Mode::Pointer, 4), 4);
-        assert_eq!(ic.paddr(&Mode::Relative, 3), 3);
+        assert_eq!(ic.paddr(&Mode::Pointer, 4).unwrap(), Some(4));
+        assert_eq!(ic.paddr(&Mode::Value, 4).unwrap(), None);
+        assert_eq!(ic.paddr(&Mode::Relative, 3).unwrap(), Some(3));
         ic.rel_base = 1;
-        assert_eq!(ic.paddr(&Mode::Relative, 3), 4);
+        assert_eq!(ic.paddr(&Mode::Relative, 3).unwrap(), Some(4));
     }
 }
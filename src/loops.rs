@@ -0,0 +1,164 @@
+//! Infinite loop detection, enabled with `Options::detect_loops`.
+//!
+//! A program that reaches the same state (instruction pointer, relative base
+//! and memory) twice with no I/O in between is deterministic from there on,
+//! so it will repeat forever. Rather than remember every state, the state at
+//! a backward jump is saved and compared with those at later backward jumps,
+//! saving again after 1, 2, 4, 8... jumps (Brent's cycle detection), so a
+//! loop is found within a few times its length and memory use is bounded.
+//! Memory is hashed incrementally as it is written, so most states are told
+//! apart without looking at memory; a matching hash is confirmed by checking
+//! that each cell written since the state was saved has its old value again.
+
+use super::memory::Memory;
+use super::Int;
+use std::collections::HashMap;
+
+/// Scramble x so nearby inputs give unrelated outputs (the splitmix64
+/// finalizer).
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Contribution of a memory cell to the memory hash. Zero cells contribute
+/// nothing, so allocated and unallocated zeroes hash alike.
+fn cell(addr: usize, value: Int) -> u64 {
+    if value == 0 {
+        0
+    } else {
+        mix(mix(addr as u64) ^ value as u64)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct LoopDetector {
+    /// XOR of the hashes of all memory cells
+    memory: u64,
+    /// ip, relative base and memory hash at the saved backward jump
+    saved: Option<(Int, Int, u64)>,
+    /// value of each cell written since the saved jump, before the first write
+    changed: HashMap<usize, Int>,
+    /// backward jumps since the saved one, and how many to make before saving
+    /// again
+    jumps: usize,
+    limit: usize,
+    /// lowest and highest instruction addresses executed since the saved jump
+    executed: Option<(Int, Int)>,
+}
+
+impl LoopDetector {
+    pub(crate) fn new(mem: &Memory) -> LoopDetector {
+        LoopDetector {
+            memory: mem.nonzero().fold(0, |h, (a, v)| h ^ cell(a, v)),
+            saved: None,
+            changed: HashMap::new(),
+            jumps: 0,
+            limit: 1,
+            executed: None,
+        }
+    }
+
+    /// Update the memory hash for value replacing old at addr.
+    pub(crate) fn write(&mut self, addr: usize, old: Int, value: Int) {
+        self.memory ^= cell(addr, old) ^ cell(addr, value);
+        if self.saved.is_some() {
+            self.changed.entry(addr).or_insert(old);
+        }
+    }
+
+    /// Record executing the instruction at addr.
+    pub(crate) fn executed(&mut self, addr: Int) {
+        self.executed = Some(match self.executed {
+            Some((lo, hi)) => (lo.min(addr), hi.max(addr)),
+            None => (addr, addr),
+        });
+    }
+
+    /// Forget the saved state, after input or output or a change made from
+    /// outside the program.
+    pub(crate) fn forget(&mut self) {
+        self.saved = None;
+        self.changed.clear();
+        self.jumps = 0;
+        self.limit = 1;
+        self.executed = None;
+    }
+
+    /// Record a backward jump to ip, with mem the memory after it. If the
+    /// state is the saved one, return the lowest and highest addresses
+    /// executed since then.
+    pub(crate) fn jump(&mut self, ip: Int, rel_base: Int, mem: &Memory) -> Option<(Int, Int)> {
+        if self.saved == Some((ip, rel_base, self.memory))
+            && self.changed.iter().all(|(&a, &v)| mem.read(a) == v)
+        {
+            let (lo, hi) = self.executed.unwrap_or((ip, ip));
+            return Some((lo.min(ip), hi.max(ip)));
+        }
+        self.jumps += 1;
+        if self.jumps == self.limit {
+            self.saved = Some((ip, rel_base, self.memory));
+            self.changed.clear();
+            self.jumps = 0;
+            self.limit *= 2;
+            self.executed = None;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test_loops {
+    use super::*;
+
+    #[test]
+    fn test_memory_hash() {
+        let mut m = Memory::new(vec![1, 0, 3]);
+        let mut d = LoopDetector::new(&m);
+        m.write(5000, 7).unwrap();
+        d.write(5000, 0, 7);
+        m.write(0, 0).unwrap();
+        d.write(0, 1, 0);
+        assert_eq!(d.memory, LoopDetector::new(&m).memory);
+        assert_ne!(
+            d.memory,
+            LoopDetector::new(&Memory::new(vec![1, 0, 3])).memory
+        );
+    }
+
+    #[test]
+    fn test_repeat() {
+        let m = Memory::new(vec![]);
+        let mut d = LoopDetector::new(&m);
+        d.executed(6);
+        assert_eq!(d.jump(3, 0, &m), None);
+        d.executed(3);
+        assert_eq!(d.jump(0, 0, &m), None);
+        d.executed(0);
+        d.executed(6);
+        assert_eq!(d.jump(3, 1, &m), None);
+        d.executed(3);
+        d.executed(0);
+        d.executed(6);
+        assert_eq!(d.jump(3, 1, &m), Some((0, 6)));
+        d.forget();
+        assert_eq!(d.jump(3, 1, &m), None);
+    }
+
+    #[test]
+    fn test_collision() {
+        let mut m = Memory::new(vec![0]);
+        let mut d = LoopDetector::new(&m);
+        assert_eq!(d.jump(0, 0, &m), None);
+        m.write(0, 5).unwrap();
+        d.write(0, 0, 5);
+        // pretend the new memory hashes like the old one
+        d.memory = 0;
+        assert_eq!(d.jump(0, 0, &m), None);
+        m.write(0, 0).unwrap();
+        d.write(0, 5, 0);
+        d.memory = 0;
+        assert_eq!(d.jump(0, 0, &m), Some((0, 0)));
+    }
+}
@@ -0,0 +1,182 @@
+use super::{Int, IntcodeError};
+use std::collections::HashMap;
+use std::fmt;
+
+/// number of cells in each page of memory beyond the program image
+pub const PAGE_SIZE: usize = 1024;
+
+/// Program memory: the program image is stored densely, while everything
+/// written beyond it is stored in pages allocated on first write, so programs
+/// can use arbitrarily large addresses without allocating everything below
+/// them. Unallocated cells read as zero.
+#[derive(Clone)]
+pub struct Memory {
+    dense: Vec<Int>,
+    pages: HashMap<usize, Box<[Int]>>,
+    limit: Option<usize>,
+}
+
+impl Memory {
+    pub fn new(image: Vec<Int>) -> Memory {
+        Memory {
+            dense: image,
+            pages: HashMap::new(),
+            limit: None,
+        }
+    }
+
+    /// Replace the contents of memory with image, keeping the limit.
+    pub fn load(&mut self, image: &[Int]) {
+        self.dense = image.to_vec();
+        self.pages.clear();
+    }
+
+    /// Limit the number of allocated cells (including the program image);
+    /// writes that would need to allocate more return an error.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// Number of cells currently allocated.
+    pub fn allocated(&self) -> usize {
+        self.dense.len() + self.pages.len() * PAGE_SIZE
+    }
+
+    /// The densely stored program image.
+    pub fn dense(&self) -> &[Int] {
+        &self.dense
+    }
+
+    pub fn read(&self, addr: usize) -> Int {
+        if addr < self.dense.len() {
+            return self.dense[addr];
+        }
+        let (page, offset) = Memory::locate(addr);
+        self.pages.get(&page).map_or(0, |p| p[offset])
+    }
+
+    /// Write value to addr and return the previous value if the cell was
+    /// already allocated.
+    pub fn write(&mut self, addr: usize, value: Int) -> Result<Option<Int>, IntcodeError> {
+        if addr < self.dense.len() {
+            let old = self.dense[addr];
+            self.dense[addr] = value;
+            return Ok(Some(old));
+        }
+        let (page, offset) = Memory::locate(addr);
+        if let Some(p) = self.pages.get_mut(&page) {
+            let old = p[offset];
+            p[offset] = value;
+            return Ok(Some(old));
+        }
+        if value == 0 {
+            // unallocated cells are already zero
+            return Ok(None);
+        }
+        if let Some(limit) = self.limit {
+            if self.allocated() + PAGE_SIZE > limit {
+                return Err(IntcodeError::MemoryLimit {
+                    address: addr as Int,
+                    limit,
+                });
+            }
+        }
+        let mut p = vec![0; PAGE_SIZE].into_boxed_slice();
+        p[offset] = value;
+        self.pages.insert(page, p);
+        Ok(None)
+    }
+
+    /// The address and value of every cell that isn't zero.
+    pub fn nonzero(&self) -> impl Iterator<Item = (usize, Int)> + '_ {
+        let dense = self.dense.iter().copied().enumerate();
+        let pages = self.pages.iter().flat_map(move |(page, p)| {
+            p.iter()
+                .enumerate()
+                .map(move |(offset, v)| (page * PAGE_SIZE + offset, *v))
+        });
+        dense.chain(pages).filter(|(_, v)| *v != 0)
+    }
+
+    /// Page number and offset within the page of addr, which must be beyond
+    /// the dense image.
+    fn locate(addr: usize) -> (usize, usize) {
+        (addr / PAGE_SIZE, addr % PAGE_SIZE)
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.dense)?;
+        if !self.pages.is_empty() {
+            let mut pages: Vec<&usize> = self.pages.keys().collect();
+            pages.sort();
+            let mut cells = f.debug_map();
+            for page in pages {
+                for (offset, value) in self.pages[page].iter().enumerate() {
+                    let addr = page * PAGE_SIZE + offset;
+                    if *value != 0 && addr >= self.dense.len() {
+                        cells.entry(&addr, value);
+                    }
+                }
+            }
+            cells.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_memory {
+    use super::*;
+
+    #[test]
+    fn test_read_write() {
+        let mut m = Memory::new(vec![1, 2, 3]);
+        assert_eq!(m.read(1), 2);
+        assert_eq!(m.read(5), 0);
+        assert_eq!(m.write(1, 5).unwrap(), Some(2));
+        assert_eq!(m.read(1), 5);
+        assert_eq!(m.write(5, 7).unwrap(), None);
+        assert_eq!(m.write(5, 8).unwrap(), Some(7));
+        assert_eq!(m.read(5), 8);
+        assert_eq!(m.allocated(), 3 + PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_far_write() {
+        let mut m = Memory::new(vec![1, 2, 3]);
+        let far = 1_000_000_000_000;
+        m.write(far, 42).unwrap();
+        assert_eq!(m.read(far), 42);
+        assert_eq!(m.read(far - 1), 0);
+        assert_eq!(m.allocated(), 3 + PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_write_zero() {
+        let mut m = Memory::new(vec![1, 2, 3]);
+        assert_eq!(m.write(5000, 0).unwrap(), None);
+        assert_eq!(m.allocated(), 3);
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut m = Memory::new(vec![1, 2, 3]);
+        m.set_limit(Some(PAGE_SIZE + 3));
+        m.write(10, 1).unwrap();
+        assert!(matches!(
+            m.write(10 * PAGE_SIZE, 1),
+            Err(IntcodeError::MemoryLimit { address: 10240, .. })
+        ));
+        m.write(11, 1).unwrap();
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut m = Memory::new(vec![1, 2, 3]);
+        assert_eq!(format!("{:?}", m), "[1, 2, 3]");
+        m.write(2000, 9).unwrap();
+        assert_eq!(format!("{:?}", m), "[1, 2, 3]{2000: 9}");
+    }
+}
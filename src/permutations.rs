@@ -21,6 +21,8 @@ impl<T: Clone> Permutator<T> {
 impl<T: Clone> Iterator for Permutator<T> {
     type Item = Vec<T>;
 
+    // usize::is_multiple_of needs Rust 1.87
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
     fn next(&mut self) -> Option<Self::Item> {
         self.count += 1;
         if self.count == 1 {
@@ -75,7 +77,7 @@ fn test_permutations() {
         vec![2, 1, 3, 0],
         vec![1, 2, 3, 0],
     ];
-    let p = Permutator::new(&vec![0, 1, 2, 3]);
+    let p = Permutator::new(&[0, 1, 2, 3]);
     let mut count = 0;
     for pp in p {
         eprintln!["{:?}", pp];
@@ -0,0 +1,344 @@
+//! Sources of input and sinks for output of Intcode programs.
+
+use super::{Int, IntcodeError};
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, Sender};
+
+/// Errors raised by an input or output port. They are converted to an
+/// `IntcodeError` for the instruction being executed.
+#[derive(Debug)]
+pub enum PortError {
+    /// no more input is available
+    Exhausted,
+    /// the input could not be parsed as an integer
+    Invalid(String),
+    /// the underlying channel was disconnected
+    Closed,
+    Io(io::Error),
+}
+
+impl PortError {
+    pub(crate) fn at(self, address: Int, instruction: Int) -> IntcodeError {
+        match self {
+            PortError::Exhausted => IntcodeError::InputExhausted {
+                address,
+                instruction,
+            },
+            PortError::Invalid(input) => IntcodeError::InvalidInput {
+                address,
+                instruction,
+                input,
+            },
+            PortError::Closed => IntcodeError::ChannelClosed {
+                address,
+                instruction,
+            },
+            PortError::Io(e) => IntcodeError::Io(e),
+        }
+    }
+}
+
+impl From<io::Error> for PortError {
+    fn from(e: io::Error) -> Self {
+        PortError::Io(e)
+    }
+}
+
+/// A source of values for input instructions.
+pub trait IntInput {
+    fn read(&mut self) -> Result<Int, PortError>;
+
+    /// Whether input is typed by a user, who should be prompted for it.
+    fn interactive(&self) -> bool {
+        false
+    }
+}
+
+/// A sink for values from output instructions.
+pub trait IntOutput {
+    fn write(&mut self, value: Int) -> Result<(), PortError>;
+
+    /// Prompt the user for interactive input.
+    fn prompt(&mut self) -> Result<(), PortError> {
+        Ok(())
+    }
+}
+
+impl<T: IntInput + ?Sized> IntInput for &mut T {
+    fn read(&mut self) -> Result<Int, PortError> {
+        (**self).read()
+    }
+
+    fn interactive(&self) -> bool {
+        (**self).interactive()
+    }
+}
+
+impl<T: IntOutput + ?Sized> IntOutput for &mut T {
+    fn write(&mut self, value: Int) -> Result<(), PortError> {
+        (**self).write(value)
+    }
+
+    fn prompt(&mut self) -> Result<(), PortError> {
+        (**self).prompt()
+    }
+}
+
+impl<T: IntInput + ?Sized> IntInput for Box<T> {
+    fn read(&mut self) -> Result<Int, PortError> {
+        (**self).read()
+    }
+
+    fn interactive(&self) -> bool {
+        (**self).interactive()
+    }
+}
+
+impl<T: IntOutput + ?Sized> IntOutput for Box<T> {
+    fn write(&mut self, value: Int) -> Result<(), PortError> {
+        (**self).write(value)
+    }
+
+    fn prompt(&mut self) -> Result<(), PortError> {
+        (**self).prompt()
+    }
+}
+
+pub enum Input<'a> {
+    None,
+    String(&'a str),
+    Reader(&'a mut dyn BufRead),
+    Channel(Receiver<Int>),
+}
+
+fn parse(s: &str) -> Result<Int, PortError> {
+    Int::from_str(s.trim()).map_err(|_| PortError::Invalid(s.trim().to_string()))
+}
+
+impl IntInput for Input<'_> {
+    fn read(&mut self) -> Result<Int, PortError> {
+        match self {
+            Input::None => Err(PortError::Exhausted),
+            Input::String(s) => parse(s),
+            Input::Reader(r) => {
+                let mut s = String::new();
+                if r.read_line(&mut s)? == 0 {
+                    return Err(PortError::Exhausted);
+                }
+                parse(&s)
+            }
+            Input::Channel(c) => c.recv().map_err(|_| PortError::Closed),
+        }
+    }
+
+    fn interactive(&self) -> bool {
+        matches!(self, Input::Reader(_))
+    }
+}
+
+pub enum Output<'a> {
+    None,
+    Writer(&'a mut dyn Write),
+    Channel(Sender<Int>),
+}
+
+impl IntOutput for Output<'_> {
+    fn write(&mut self, value: Int) -> Result<(), PortError> {
+        match self {
+            Output::None => Ok(()),
+            Output::Writer(w) => Ok(writeln!(w, "{}", value)?),
+            Output::Channel(c) => c.send(value).map_err(|_| PortError::Closed),
+        }
+    }
+
+    fn prompt(&mut self) -> Result<(), PortError> {
+        if let Output::Writer(w) = self {
+            w.write_all(b"?")?;
+            w.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Values are consumed from the front of the queue.
+impl IntInput for VecDeque<Int> {
+    fn read(&mut self) -> Result<Int, PortError> {
+        self.pop_front().ok_or(PortError::Exhausted)
+    }
+}
+
+impl IntOutput for VecDeque<Int> {
+    fn write(&mut self, value: Int) -> Result<(), PortError> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+impl IntOutput for Vec<Int> {
+    fn write(&mut self, value: Int) -> Result<(), PortError> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+/// Input from a closure, which returns None when input is exhausted.
+pub struct FnInput<F>(pub F);
+
+impl<F: FnMut() -> Option<Int>> IntInput for FnInput<F> {
+    fn read(&mut self) -> Result<Int, PortError> {
+        (self.0)().ok_or(PortError::Exhausted)
+    }
+}
+
+/// Output to a closure.
+pub struct FnOutput<F>(pub F);
+
+impl<F: FnMut(Int)> IntOutput for FnOutput<F> {
+    fn write(&mut self, value: Int) -> Result<(), PortError> {
+        (self.0)(value);
+        Ok(())
+    }
+}
+
+/// Input from an iterator.
+pub struct IterInput<I>(pub I);
+
+impl<I: Iterator<Item = Int>> IntInput for IterInput<I> {
+    fn read(&mut self) -> Result<Int, PortError> {
+        self.0.next().ok_or(PortError::Exhausted)
+    }
+}
+
+/// Input for programs that read text: each line read from the reader is
+/// converted to its character codes followed by a newline (10).
+pub struct AsciiInput<R> {
+    reader: R,
+    pending: VecDeque<Int>,
+}
+
+impl<R: BufRead> AsciiInput<R> {
+    pub fn new(reader: R) -> AsciiInput<R> {
+        AsciiInput {
+            reader,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<R: BufRead> IntInput for AsciiInput<R> {
+    fn read(&mut self) -> Result<Int, PortError> {
+        if self.pending.is_empty() {
+            let mut s = String::new();
+            if self.reader.read_line(&mut s)? == 0 {
+                return Err(PortError::Exhausted);
+            }
+            let line = s.trim_end_matches(['\n', '\r']);
+            self.pending.extend(line.chars().map(|c| c as Int));
+            self.pending.push_back(10);
+        }
+        Ok(self.pending.pop_front().unwrap())
+    }
+
+    fn interactive(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Output for programs that write text: values in the ASCII range are
+/// written as characters, anything else as a number on its own line.
+pub struct AsciiOutput<W> {
+    writer: W,
+}
+
+impl<W: Write> AsciiOutput<W> {
+    pub fn new(writer: W) -> AsciiOutput<W> {
+        AsciiOutput { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> IntOutput for AsciiOutput<W> {
+    fn write(&mut self, value: Int) -> Result<(), PortError> {
+        if (0..128).contains(&value) {
+            self.writer.write_all(&[value as u8])?;
+        } else {
+            writeln!(self.writer, "{}", value)?;
+        }
+        Ok(())
+    }
+
+    /// The program writes its own prompts, so just make sure they are shown.
+    fn prompt(&mut self) -> Result<(), PortError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod test_ports {
+    use super::*;
+
+    #[test]
+    fn test_queue() {
+        let mut i: VecDeque<Int> = VecDeque::from(vec![1, 2]);
+        assert_eq!(IntInput::read(&mut i).unwrap(), 1);
+        assert_eq!(IntInput::read(&mut i).unwrap(), 2);
+        assert!(matches!(IntInput::read(&mut i), Err(PortError::Exhausted)));
+        let mut o: Vec<Int> = Vec::new();
+        o.write(3).unwrap();
+        assert_eq!(o, vec![3]);
+    }
+
+    #[test]
+    fn test_reader() {
+        let mut r = io::Cursor::new("1\nx\n");
+        let mut i = Input::Reader(&mut r);
+        assert!(i.interactive());
+        assert_eq!(i.read().unwrap(), 1);
+        assert!(matches!(i.read(), Err(PortError::Invalid(ref s)) if s == "x"));
+        assert!(matches!(i.read(), Err(PortError::Exhausted)));
+    }
+
+    #[test]
+    fn test_fn() {
+        let mut n = 0;
+        let mut i = FnInput(|| {
+            n += 1;
+            Some(n)
+        });
+        assert_eq!(i.read().unwrap(), 1);
+        assert_eq!(i.read().unwrap(), 2);
+        let mut sum = 0;
+        let mut o = FnOutput(|v| sum += v);
+        o.write(2).unwrap();
+        o.write(3).unwrap();
+        assert_eq!(sum, 5);
+    }
+
+    #[test]
+    fn test_ascii_input() {
+        let mut i = AsciiInput::new(io::Cursor::new("AB\r\nC\n"));
+        assert!(i.interactive());
+        assert_eq!(i.read().unwrap(), 65);
+        assert!(!i.interactive());
+        assert_eq!(i.read().unwrap(), 66);
+        assert_eq!(i.read().unwrap(), 10);
+        assert_eq!(i.read().unwrap(), 67);
+        assert_eq!(i.read().unwrap(), 10);
+        assert!(matches!(i.read(), Err(PortError::Exhausted)));
+    }
+
+    #[test]
+    fn test_ascii_output() {
+        let mut o = AsciiOutput::new(Vec::new());
+        for v in &[72, 105, 10, 1219070632396] {
+            IntOutput::write(&mut o, *v).unwrap();
+        }
+        assert_eq!(o.into_inner(), b"Hi\n1219070632396\n");
+    }
+}
@@ -0,0 +1,298 @@
+//! Execution profiles, collected when `Options::profile` is set.
+
+use super::{Int, Operation};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// number of addresses and blocks listed in the text report
+const TOP: usize = 10;
+
+/// Statistics for an executed address.
+#[derive(Clone, Debug, Default)]
+struct Site {
+    count: u64,
+    /// operation and length of the instruction last executed here
+    op: Option<Operation>,
+    len: Int,
+}
+
+/// Counts of the instructions executed by a program.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    steps: u64,
+    sites: BTreeMap<Int, Site>,
+    ops: [u64; Operation::ALL.len()],
+    /// addresses where control flow may enter other than by falling through
+    /// from the previous instruction
+    leaders: BTreeSet<Int>,
+    high_water: usize,
+}
+
+/// A straight-line run of instructions, entered only at its start and left
+/// only from its last instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    /// address of the first instruction
+    pub start: Int,
+    /// address of the last instruction
+    pub end: Int,
+    /// number of times the block was entered
+    pub entries: u64,
+    /// number of instructions executed in the block
+    pub steps: u64,
+}
+
+impl Profile {
+    pub(crate) fn new(allocated: usize) -> Profile {
+        Profile {
+            steps: 0,
+            sites: BTreeMap::new(),
+            ops: [0; Operation::ALL.len()],
+            leaders: BTreeSet::new(),
+            high_water: allocated,
+        }
+    }
+
+    /// Record executing op at address, after which execution continues at
+    /// next and allocated memory cells are in use.
+    pub(crate) fn record(&mut self, address: Int, op: Operation, next: Int, allocated: usize) {
+        if self.steps == 0 {
+            self.leaders.insert(address);
+        }
+        self.steps += 1;
+        let site = self.sites.entry(address).or_default();
+        site.count += 1;
+        site.op = Some(op);
+        site.len = op.len() as Int;
+        if let Some(i) = Operation::ALL.iter().position(|o| *o == op) {
+            self.ops[i] += 1;
+        }
+        if op == Operation::JumpNotZero || op == Operation::JumpZero {
+            self.leaders.insert(next);
+            if let Some(after) = address.checked_add(site.len) {
+                self.leaders.insert(after);
+            }
+        }
+        self.high_water = self.high_water.max(allocated);
+    }
+
+    /// Total number of instructions executed.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Largest number of memory cells allocated at once.
+    pub fn high_water(&self) -> usize {
+        self.high_water
+    }
+
+    /// Number of times each operation was executed, by mnemonic, for those
+    /// executed at least once.
+    pub fn op_counts(&self) -> Vec<(String, u64)> {
+        Operation::ALL
+            .iter()
+            .zip(self.ops.iter())
+            .filter(|(_, n)| **n > 0)
+            .map(|(op, n)| (op.to_string().trim_end().to_string(), *n))
+            .collect()
+    }
+
+    /// Number of times the instruction at each address was executed, in
+    /// address order.
+    pub fn address_counts(&self) -> Vec<(Int, u64)> {
+        self.sites.iter().map(|(a, s)| (*a, s.count)).collect()
+    }
+
+    /// The n most executed addresses, most executed first.
+    pub fn hottest(&self, n: usize) -> Vec<(Int, u64)> {
+        let mut counts = self.address_counts();
+        counts.sort_by_key(|(a, c)| (std::cmp::Reverse(*c), *a));
+        counts.truncate(n);
+        counts
+    }
+
+    /// The executed basic blocks, in address order. Blocks start at the
+    /// first instruction executed and at jump targets and fall-throughs, and
+    /// end at jumps and END.
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut open = false;
+        for (addr, site) in self.sites.iter() {
+            let continues = open
+                && !self.leaders.contains(addr)
+                && blocks.last().is_some_and(|b| {
+                    let last = &self.sites[&b.end];
+                    b.end.checked_add(last.len) == Some(*addr)
+                });
+            if continues {
+                let block = blocks.last_mut().unwrap();
+                block.end = *addr;
+                block.steps += site.count;
+            } else {
+                blocks.push(Block {
+                    start: *addr,
+                    end: *addr,
+                    entries: site.count,
+                    steps: site.count,
+                });
+            }
+            open = !matches!(
+                site.op,
+                Some(Operation::JumpNotZero) | Some(Operation::JumpZero) | Some(Operation::End)
+            );
+        }
+        blocks
+    }
+
+    /// The n blocks in which the most instructions were executed.
+    pub fn hottest_blocks(&self, n: usize) -> Vec<Block> {
+        let mut blocks = self.blocks();
+        blocks.sort_by_key(|b| (std::cmp::Reverse(b.steps), b.start));
+        blocks.truncate(n);
+        blocks
+    }
+
+    fn mnemonic(&self, addr: Int) -> String {
+        self.sites[&addr]
+            .op
+            .map_or(String::new(), |op| op.to_string().trim_end().to_string())
+    }
+
+    /// The whole profile as a JSON object.
+    pub fn to_json(&self) -> String {
+        let ops: Vec<String> = self
+            .op_counts()
+            .iter()
+            .map(|(op, n)| format!["\"{}\":{}", op, n])
+            .collect();
+        let addresses: Vec<String> = self
+            .address_counts()
+            .iter()
+            .map(|(a, n)| {
+                format![
+                    "{{\"address\":{},\"op\":\"{}\",\"count\":{}}}",
+                    a,
+                    self.mnemonic(*a),
+                    n
+                ]
+            })
+            .collect();
+        let blocks: Vec<String> = self
+            .blocks()
+            .iter()
+            .map(|b| {
+                format![
+                    "{{\"start\":{},\"end\":{},\"entries\":{},\"steps\":{}}}",
+                    b.start, b.end, b.entries, b.steps
+                ]
+            })
+            .collect();
+        format![
+            "{{\"steps\":{},\"memory_high_water\":{},\"operations\":{{{}}},\"addresses\":[{}],\"blocks\":[{}]}}\n",
+            self.steps,
+            self.high_water,
+            ops.join(","),
+            addresses.join(","),
+            blocks.join(",")
+        ]
+    }
+
+    /// Per-address counts as CSV, with a header line.
+    pub fn to_csv(&self) -> String {
+        let mut s = "address,op,count\n".to_string();
+        for (a, n) in self.address_counts() {
+            s += &format!["{},{},{}\n", a, self.mnemonic(a), n];
+        }
+        s
+    }
+}
+
+/// A text report of the totals and the hottest addresses and blocks.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "steps: {}", self.steps)?;
+        writeln!(f, "memory high-water mark: {} cells", self.high_water)?;
+        writeln!(f, "operations:")?;
+        for (op, n) in self.op_counts() {
+            writeln!(
+                f,
+                "  {:3} {:>12} {:5.1}%",
+                op,
+                n,
+                100.0 * n as f64 / self.steps as f64
+            )?;
+        }
+        writeln!(f, "hottest addresses:")?;
+        for (a, n) in self.hottest(TOP) {
+            writeln!(f, "  {:04} {:3} {:>12}", a, self.mnemonic(a), n)?;
+        }
+        writeln!(f, "hottest blocks:")?;
+        for b in self.hottest_blocks(TOP) {
+            writeln!(
+                f,
+                "  {:04}-{:04} entries {:>10} steps {:>12}",
+                b.start, b.end, b.entries, b.steps
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_profile {
+    use super::*;
+    use crate::{Options, Program};
+
+    /// Count down from 3, then halt.
+    fn profile() -> Profile {
+        let mut p = Program::from_code(vec![1101, 3, 0, 20, 1001, 20, -1, 20, 1005, 20, 4, 99]);
+        p.set_options(Options {
+            profile: true,
+            ..Options::default()
+        });
+        p.run_until_io(None).unwrap();
+        p.profile().unwrap().clone()
+    }
+
+    #[test]
+    fn test_counts() {
+        let p = profile();
+        assert_eq!(p.steps(), 8);
+        assert_eq!(p.high_water(), 12 + crate::memory::PAGE_SIZE);
+        assert_eq!(
+            p.op_counts(),
+            vec![
+                ("END".to_string(), 1),
+                ("ADD".to_string(), 4),
+                ("JNZ".to_string(), 3)
+            ]
+        );
+        assert_eq!(p.hottest(2), vec![(4, 3), (8, 3)]);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let block = |start, end, entries, steps| Block {
+            start,
+            end,
+            entries,
+            steps,
+        };
+        assert_eq!(
+            profile().blocks(),
+            vec![block(0, 0, 1, 1), block(4, 8, 3, 6), block(11, 11, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_reports() {
+        let p = profile();
+        assert_eq!(
+            p.to_csv(),
+            "address,op,count\n0,ADD,1\n4,ADD,3\n8,JNZ,3\n11,END,1\n"
+        );
+        assert!(p.to_json().starts_with(
+            "{\"steps\":8,\"memory_high_water\":1036,\"operations\":{\"END\":1,\"ADD\":4,\"JNZ\":3},"
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+//! Tracing of executed instructions, enabled by passing a `Tracer` to
+//! `Program::exe`, `Program::step` or `Program::run_until_io`.
+
+use super::disassemble::param;
+use super::{Int, Mode};
+use std::io;
+use std::io::prelude::*;
+
+/// A parameter of a traced instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub mode: Mode,
+    /// the parameter as stored in memory before the instruction executed
+    pub raw: Int,
+    /// the address referred to, except in immediate mode
+    pub address: Option<Int>,
+    /// the value read, except for the parameter written to
+    pub value: Option<Int>,
+}
+
+/// An executed instruction, with its operands resolved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    /// number of instructions executed before this one
+    pub step: usize,
+    pub address: Int,
+    pub instruction: Int,
+    pub mnemonic: &'static str,
+    /// relative base the parameters were resolved with
+    pub rel_base: Int,
+    pub params: Vec<Param>,
+    /// address written and the value written to it
+    pub write: Option<(Int, Int)>,
+    pub input: Option<Int>,
+    pub output: Option<Int>,
+    /// whether the instruction is a jump that was taken
+    pub jumped: bool,
+    /// address of the next instruction
+    pub next: Int,
+}
+
+/// Receiver of an event for each executed instruction. Instructions that
+/// fail are not traced.
+pub trait Tracer {
+    fn trace(&mut self, event: &TraceEvent) -> io::Result<()>;
+}
+
+impl<F: FnMut(&TraceEvent)> Tracer for F {
+    fn trace(&mut self, event: &TraceEvent) -> io::Result<()> {
+        self(event);
+        Ok(())
+    }
+}
+
+/// Writes each event as a line of text, such as
+/// `0004: ADD [9]=3, #2, rb+1->[10] ; [10] = 5`.
+pub struct TextTracer<W> {
+    writer: W,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(writer: W) -> TextTracer<W> {
+        TextTracer { writer }
+    }
+}
+
+impl TextTracer<io::Stderr> {
+    pub fn stderr() -> TextTracer<io::Stderr> {
+        TextTracer::new(io::stderr())
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) -> io::Result<()> {
+        let params: Vec<String> = event
+            .params
+            .iter()
+            .map(|p| {
+                let mut s = param(&p.mode, p.raw);
+                if let (Mode::Relative, Some(a)) = (&p.mode, p.address) {
+                    s += &format!["->[{}]", a];
+                }
+                if let (Mode::Pointer | Mode::Relative, Some(v)) = (&p.mode, p.value) {
+                    s += &format!["={}", v];
+                }
+                s
+            })
+            .collect();
+        write!(self.writer, "{:04}: {}", event.address, event.mnemonic)?;
+        if !params.is_empty() {
+            write!(self.writer, " {}", params.join(", "))?;
+        }
+        if let Some((a, v)) = event.write {
+            write!(self.writer, " ; [{}] = {}", a, v)?;
+        }
+        if let Some(i) = event.input {
+            write!(self.writer, " ; input {}", i)?;
+        }
+        if let Some(o) = event.output {
+            write!(self.writer, " ; output {}", o)?;
+        }
+        if event.jumped {
+            write!(self.writer, " ; next {}", event.next)?;
+        }
+        writeln!(self.writer)
+    }
+}
+
+/// Writes each event as a JSON object on its own line.
+pub struct JsonTracer<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(writer: W) -> JsonTracer<W> {
+        JsonTracer { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn json_option(v: Option<Int>) -> String {
+    v.map_or("null".to_string(), |v| v.to_string())
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) -> io::Result<()> {
+        let params: Vec<String> = event
+            .params
+            .iter()
+            .map(|p| {
+                format![
+                    "{{\"mode\":\"{}\",\"raw\":{},\"address\":{},\"value\":{}}}",
+                    match p.mode {
+                        Mode::Pointer => "position",
+                        Mode::Value => "immediate",
+                        Mode::Relative => "relative",
+                    },
+                    p.raw,
+                    json_option(p.address),
+                    json_option(p.value)
+                ]
+            })
+            .collect();
+        writeln!(
+            self.writer,
+            "{{\"step\":{},\"address\":{},\"instruction\":{},\"op\":\"{}\",\"rel_base\":{},\
+             \"params\":[{}],\"write\":{},\"input\":{},\"output\":{},\"jumped\":{},\
+             \"next\":{}}}",
+            event.step,
+            event.address,
+            event.instruction,
+            event.mnemonic,
+            event.rel_base,
+            params.join(","),
+            event.write.map_or("null".to_string(), |(a, v)| format![
+                "{{\"address\":{},\"value\":{}}}",
+                a, v
+            ]),
+            json_option(event.input),
+            json_option(event.output),
+            event.jumped,
+            event.next
+        )
+    }
+}
+
+#[cfg(test)]
+mod test_trace {
+    use super::*;
+    use crate::Program;
+    use std::collections::VecDeque;
+
+    fn trace<T: Tracer>(code: Vec<Int>, tracer: &mut T) {
+        let mut p = Program::from_code(code);
+        p.exe(Some(tracer), VecDeque::from(vec![7]), Vec::new())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_text() {
+        let mut t = TextTracer::new(Vec::new());
+        trace(
+            vec![109, 2, 22201, -2, 0, 1, 3, 0, 1105, 1, 12, 0, 99],
+            &mut t,
+        );
+        assert_eq!(
+            String::from_utf8(t.writer).unwrap(),
+            "0000: REL #2\n\
+             0002: ADD rb-2->[0]=109, rb+0->[2]=22201, rb+1->[3] ; [3] = 22310\n\
+             0006: INP [0] ; [0] = 7 ; input 7\n\
+             0008: JNZ #1, #12 ; next 12\n\
+             0012: END\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let mut t = JsonTracer::new(Vec::new());
+        trace(vec![4, 3, 99, 42], &mut t);
+        assert_eq!(
+            String::from_utf8(t.into_inner()).unwrap(),
+            "{\"step\":0,\"address\":0,\"instruction\":4,\"op\":\"OUT\",\"rel_base\":0,\
+             \"params\":[{\"mode\":\"position\",\"raw\":3,\"address\":3,\"value\":42}],\
+             \"write\":null,\"input\":null,\"output\":42,\"jumped\":false,\"next\":2}\n\
+             {\"step\":1,\"address\":2,\"instruction\":99,\"op\":\"END\",\"rel_base\":0,\
+             \"params\":[],\"write\":null,\"input\":null,\"output\":null,\"jumped\":false,\"next\":2}\n"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Memory watchpoints, which pause execution when an instruction reads or
+//! writes a watched address.
+
+use super::Int;
+use std::fmt;
+use std::str::FromStr;
+
+/// Kind of memory access.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    /// Whether watching for self is triggered by access, which is either
+    /// `Read` or `Write`.
+    fn triggers(&self, access: Access) -> bool {
+        *self == Access::ReadWrite || *self == access
+    }
+}
+
+/// Watch on the addresses start to end inclusive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: Int,
+    pub end: Int,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    pub(crate) fn triggers(&self, target: Int, access: Access) -> bool {
+        target >= self.start && target <= self.end && self.access.triggers(access)
+    }
+}
+
+/// An access to watched memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    /// address of the instruction that accessed memory
+    pub address: Int,
+    /// address accessed
+    pub target: Int,
+    /// value before the access
+    pub old: Int,
+    /// value after the access (the same as old for reads)
+    pub new: Int,
+    /// either `Read` or `Write`
+    pub access: Access,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.access {
+            Access::Read => write!(f, "{}: read [{}] = {}", self.address, self.target, self.old),
+            _ => write!(
+                f,
+                "{}: write [{}] {} -> {}",
+                self.address, self.target, self.old, self.new
+            ),
+        }
+    }
+}
+
+/// Parse an address range written as `ADDR` or `START-END` (inclusive).
+pub fn parse_range(s: &str) -> Option<(Int, Int)> {
+    let s = s.trim();
+    match s.split_once('-') {
+        Some((start, end)) => {
+            let start = Int::from_str(start.trim()).ok()?;
+            let end = Int::from_str(end.trim()).ok()?;
+            if start <= end {
+                Some((start, end))
+            } else {
+                None
+            }
+        }
+        None => Int::from_str(s).ok().map(|a| (a, a)),
+    }
+}
+
+#[cfg(test)]
+mod test_watch {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("1023"), Some((1023, 1023)));
+        assert_eq!(parse_range(" 10 - 20 "), Some((10, 20)));
+        assert_eq!(parse_range("20-10"), None);
+        assert_eq!(parse_range("x"), None);
+    }
+}
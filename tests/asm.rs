@@ -0,0 +1,41 @@
+use intcode::asm::assemble;
+use intcode::disassemble::disassemble;
+use intcode::{Input, Int, Program};
+use std::fs::File;
+use std::io;
+
+#[test]
+fn test_countdown() {
+    let source = "
+        ; count down from 3, printing each number
+                ADD #3, #0, [count]
+        loop:   OUT [count]
+                ADD [count], #-1, [count]
+                JNZ [count], #loop
+                END
+        count:  DATA 0
+    ";
+    let mut ic = Program::from_code(assemble(source).unwrap());
+    let mut output: Vec<Int> = Vec::new();
+    ic.exe(None, Input::None, &mut output)
+        .expect("execution error");
+    assert_eq![output, vec![3, 2, 1]];
+}
+
+/// Disassembling and reassembling a program gives back the same code.
+#[test]
+fn test_round_trip() {
+    for day in &[2, 5, 7, 9, 11, 13, 15, 17, 19] {
+        let f = File::open(format!["input/day{}.int", day]).unwrap();
+        let ic = Program::new(io::BufReader::new(f));
+        let listing: Vec<String> = disassemble(&ic, 0, Int::MAX)
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let code = assemble(&listing.join("\n")).unwrap();
+        let orig: Vec<Int> = (0..code.len() as Int)
+            .map(|a| ic.peek(a).unwrap())
+            .collect();
+        assert_eq![code, orig, "day {}", day];
+    }
+}
@@ -0,0 +1,39 @@
+//! AoC 2019 day 17: https://adventofcode.com/2019/day/17
+
+extern crate intcode;
+use intcode::ports::AsciiOutput;
+use intcode::{Input, Program};
+use std::fs::File;
+use std::io;
+
+#[test]
+fn part1() {
+    let f = File::open("input/day17.int").unwrap();
+    let reader = io::BufReader::new(f);
+    let mut ic = Program::new(reader);
+    let mut out = AsciiOutput::new(Vec::new());
+    ic.exe(None, Input::None, &mut out)
+        .expect("execution error");
+
+    // sum the alignment parameters (x * y) of all scaffold intersections
+    let view = String::from_utf8(out.into_inner()).unwrap();
+    let grid: Vec<&[u8]> = view
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.as_bytes())
+        .collect();
+    let mut sum = 0;
+    for y in 1..grid.len() - 1 {
+        for x in 1..grid[y].len() - 1 {
+            if grid[y][x] == b'#'
+                && grid[y - 1][x] == b'#'
+                && grid[y + 1][x] == b'#'
+                && grid[y][x - 1] == b'#'
+                && grid[y][x + 1] == b'#'
+            {
+                sum += x * y;
+            }
+        }
+    }
+    assert_eq![sum, 5788];
+}
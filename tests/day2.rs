@@ -13,12 +13,12 @@ fn main() {
 
     // before running the program, replace position 1 with the value 12 and
     // replace position 2 with the value 2.
-    ic.poke(1, 12);
-    ic.poke(2, 2);
+    ic.poke(1, 12).unwrap();
+    ic.poke(2, 2).unwrap();
 
-    ic.exe(0, false, Input::None, Output::None)
+    ic.exe(None, Input::None, Output::None)
         .expect("execution error");
 
     // What value is left at position 0 after the program halts?
-    assert_eq![ic.peek(0), 4462686];
+    assert_eq![ic.peek(0).unwrap(), 4462686];
 }
@@ -2,12 +2,9 @@
 
 extern crate intcode;
 use intcode::permutations::Permutator;
-use intcode::{Input, Int, Output, Program};
-use std::convert::TryInto;
+use intcode::{Input, Int, Output, Program, RunState};
 use std::fs::File;
 use std::io;
-use std::io::Write;
-use std::str::FromStr;
 use std::sync::mpsc::channel;
 
 fn amp(program: &mut Program, phases: &[Int]) -> i64 {
@@ -17,19 +14,20 @@ fn amp(program: &mut Program, phases: &[Int]) -> i64 {
         let (otx, orx) = channel::<Int>();
         itx.send(*p).unwrap();
         itx.send(sig).unwrap();
+        program.reset();
         program
-            .exe(0, false, Input::Channel(irx), Output::Channel(otx))
+            .exe(None, Input::Channel(irx), Output::Channel(otx))
             .expect("execution error");
         sig = orx.recv().unwrap();
     }
-    sig.try_into().unwrap()
+    sig
 }
 
 #[test]
 fn test_amp_1() {
     let code = io::Cursor::new("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
     let mut ic = Program::new(code);
-    assert_eq![amp(&mut ic, &vec![4, 3, 2, 1, 0]), 43210];
+    assert_eq![amp(&mut ic, &[4, 3, 2, 1, 0]), 43210];
 }
 
 #[test]
@@ -37,7 +35,7 @@ fn test_amp_2() {
     let code =
         io::Cursor::new("3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0");
     let mut ic = Program::new(code);
-    assert_eq![amp(&mut ic, &vec![0, 1, 2, 3, 4]), 54321];
+    assert_eq![amp(&mut ic, &[0, 1, 2, 3, 4]), 54321];
 }
 
 #[test]
@@ -45,7 +43,7 @@ fn test_amp_3() {
     let code =
         io::Cursor::new("3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0");
     let mut ic = Program::new(code);
-    assert_eq![amp(&mut ic, &vec![1, 0, 4, 3, 2]), 65210];
+    assert_eq![amp(&mut ic, &[1, 0, 4, 3, 2]), 65210];
 }
 
 #[test]
@@ -54,7 +52,7 @@ fn part1() {
     let reader = io::BufReader::new(f);
     let mut ic = Program::new(reader);
 
-    let p = Permutator::new(&vec![0, 1, 2, 3, 4]);
+    let p = Permutator::new(&[0, 1, 2, 3, 4]);
     let mut max = 0;
     for pp in p {
         let sig = amp(&mut ic, &pp);
@@ -65,3 +63,51 @@ fn part1() {
     }
     assert_eq![max, 567045];
 }
+
+/// Run the amplifiers in a feedback loop on a single thread, passing each
+/// output on to the next amplifier until the last one halts.
+fn feedback(program: &Program, phases: &[Int]) -> Int {
+    let mut amps: Vec<Program> = phases
+        .iter()
+        .map(|p| {
+            let mut a = program.clone();
+            a.push_input(*p);
+            a
+        })
+        .collect();
+    let mut sig: Int = 0;
+    let mut halted = false;
+    while !halted {
+        for a in amps.iter_mut() {
+            a.push_input(sig);
+            match a.run_until_io(None).expect("execution error") {
+                RunState::Output(o) => sig = o,
+                RunState::Halted => halted = true,
+                state => panic!["unexpected state {:?}", state],
+            }
+        }
+    }
+    sig
+}
+
+#[test]
+fn test_feedback_1() {
+    let code = io::Cursor::new(
+        "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+    );
+    let ic = Program::new(code);
+    assert_eq![feedback(&ic, &[9, 8, 7, 6, 5]), 139629729];
+}
+
+#[test]
+fn part2() {
+    let f = File::open("input/day7.int").unwrap();
+    let reader = io::BufReader::new(f);
+    let ic = Program::new(reader);
+
+    let max = Permutator::new(&[5, 6, 7, 8, 9])
+        .map(|pp| feedback(&ic, &pp))
+        .max()
+        .unwrap();
+    assert_eq![max, 39016654];
+}
@@ -12,7 +12,7 @@ fn run_day9(input: &[u8], output: &[u8]) {
     let mut inc = io::Cursor::new(input);
     let mut outc = io::Cursor::new(vec![0; output.len()]);
 
-    ic.exe(0, false, Input::Reader(&mut inc), Output::Writer(&mut outc))
+    ic.exe(None, Input::Reader(&mut inc), Output::Writer(&mut outc))
         .expect("execution error");
     assert_eq![outc.get_ref().as_slice(), output];
 }
@@ -1,4 +1,9 @@
-use intcode::{Input, Int, Output, Program};
+use intcode::ports::{FnOutput, IterInput};
+use intcode::{
+    Access, Cancel, Input, Int, IntcodeError, Options, Output, Overflow, Program, RunState,
+    TraceEvent, WatchHit,
+};
+use std::collections::VecDeque;
 use std::io;
 use std::sync::mpsc::channel;
 
@@ -6,18 +11,18 @@ use std::sync::mpsc::channel;
 fn test_add() {
     let code = io::Cursor::new("1,4,5,4,11,88");
     let mut ic = Program::new(code);
-    ic.exe(0, false, Input::None, Output::None)
+    ic.exe(None, Input::None, Output::None)
         .expect("execution error");
-    assert_eq![ic.peek(4), 99];
+    assert_eq![ic.peek(4).unwrap(), 99];
 }
 
 #[test]
 fn test_mul() {
     let code = io::Cursor::new("2,4,5,4,3,33");
     let mut ic = Program::new(code);
-    ic.exe(0, false, Input::None, Output::None)
+    ic.exe(None, Input::None, Output::None)
         .expect("execution error");
-    assert_eq![ic.peek(4), 99];
+    assert_eq![ic.peek(4).unwrap(), 99];
 }
 
 #[test]
@@ -25,9 +30,9 @@ fn test_input() {
     let code = io::Cursor::new("3,2,0");
     let mut ic = Program::new(code);
     let mut input = io::Cursor::new("99");
-    ic.exe(0, false, Input::Reader(&mut input), Output::None)
+    ic.exe(None, Input::Reader(&mut input), Output::None)
         .expect("execution error");
-    assert_eq![ic.peek(2), 99];
+    assert_eq![ic.peek(2).unwrap(), 99];
 }
 
 #[test]
@@ -35,10 +40,10 @@ fn test_input2() {
     let code = io::Cursor::new("3,0,3,4,0");
     let mut ic = Program::new(code);
     let mut input = io::Cursor::new("-1\n99\n");
-    ic.exe(0, false, Input::Reader(&mut input), Output::None)
+    ic.exe(None, Input::Reader(&mut input), Output::None)
         .expect("execution error");
-    assert_eq![ic.peek(0), -1];
-    assert_eq![ic.peek(4), 99];
+    assert_eq![ic.peek(0).unwrap(), -1];
+    assert_eq![ic.peek(4).unwrap(), 99];
 }
 
 #[test]
@@ -47,9 +52,9 @@ fn test_input_chan() {
     let mut ic = Program::new(code);
     let (tx, rx) = channel::<Int>();
     tx.send(99).unwrap();
-    ic.exe(0, false, Input::Channel(rx), Output::None)
+    ic.exe(None, Input::Channel(rx), Output::None)
         .expect("execution error");
-    assert_eq![ic.peek(2), 99];
+    assert_eq![ic.peek(2).unwrap(), 99];
 }
 
 #[test]
@@ -57,7 +62,7 @@ fn test_output() {
     let code = io::Cursor::new("4,2,99");
     let mut ic = Program::new(code);
     let mut output = io::Cursor::new(vec![0; 3]);
-    ic.exe(0, false, Input::None, Output::Writer(&mut output))
+    ic.exe(None, Input::None, Output::Writer(&mut output))
         .expect("execution error");
     assert_eq![output.get_ref(), b"99\n"];
 }
@@ -67,7 +72,7 @@ fn test_output_chan() {
     let code = io::Cursor::new("4,2,99");
     let mut ic = Program::new(code);
     let (tx, rx) = channel::<Int>();
-    ic.exe(0, false, Input::None, Output::Channel(tx))
+    ic.exe(None, Input::None, Output::Channel(tx))
         .expect("execution error");
     assert_eq![rx.recv().unwrap(), 99];
 }
@@ -77,7 +82,7 @@ fn test_jnz() {
     let code = io::Cursor::new("5,0,4,99,4,6,99");
     let mut ic = Program::new(code);
     let mut output = io::Cursor::new(vec![0; 3]);
-    ic.exe(0, false, Input::None, Output::Writer(&mut output))
+    ic.exe(None, Input::None, Output::Writer(&mut output))
         .expect("execution error");
     assert_eq![output.get_ref(), b"99\n"];
 }
@@ -87,7 +92,7 @@ fn test_jz() {
     let code = io::Cursor::new("106,0,4,99,4,6,99");
     let mut ic = Program::new(code);
     let mut output = io::Cursor::new(vec![0; 3]);
-    ic.exe(0, false, Input::None, Output::Writer(&mut output))
+    ic.exe(None, Input::None, Output::Writer(&mut output))
         .expect("execution error");
     assert_eq![output.get_ref(), b"99\n"];
 }
@@ -96,7 +101,633 @@ fn test_jz() {
 fn test_rel_base() {
     let code = io::Cursor::new("9,0,99");
     let mut ic = Program::new(code);
-    ic.exe(0, false, Input::None, Output::None)
+    ic.exe(None, Input::None, Output::None)
         .expect("execution error");
     assert_eq![ic.rel_base(), 9];
 }
+
+#[test]
+fn test_invalid_opcode() {
+    let code = io::Cursor::new("1101,1,1,5,42,0");
+    let mut ic = Program::new(code);
+    let err = ic.exe(None, Input::None, Output::None).unwrap_err();
+    assert!(matches!(
+        err,
+        IntcodeError::InvalidOpcode {
+            address: 4,
+            instruction: 42
+        }
+    ));
+}
+
+#[test]
+fn test_invalid_mode() {
+    let code = io::Cursor::new("301,0,0,0,99");
+    let mut ic = Program::new(code);
+    let err = ic.exe(None, Input::None, Output::None).unwrap_err();
+    assert!(matches!(
+        err,
+        IntcodeError::InvalidMode {
+            address: 0,
+            instruction: 301
+        }
+    ));
+}
+
+#[test]
+fn test_negative_address() {
+    let code = io::Cursor::new("1,-3,0,0,99");
+    let mut ic = Program::new(code);
+    let err = ic.exe(None, Input::None, Output::None).unwrap_err();
+    assert!(matches!(err, IntcodeError::NegativeAddress { address: -3 }));
+}
+
+#[test]
+fn test_write_to_immediate() {
+    let code = io::Cursor::new("11101,1,1,5,99");
+    let mut ic = Program::new(code);
+    let err = ic.exe(None, Input::None, Output::None).unwrap_err();
+    assert!(matches!(
+        err,
+        IntcodeError::WriteToImmediate {
+            address: 0,
+            instruction: 11101
+        }
+    ));
+}
+
+#[test]
+fn test_input_exhausted() {
+    let code = io::Cursor::new("3,0,3,0,99");
+    let mut ic = Program::new(code);
+    let mut input = io::Cursor::new("7\n");
+    let err = ic
+        .exe(None, Input::Reader(&mut input), Output::None)
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        IntcodeError::InputExhausted {
+            address: 2,
+            instruction: 3
+        }
+    ));
+}
+
+#[test]
+fn test_invalid_input() {
+    let code = io::Cursor::new("3,0,99");
+    let mut ic = Program::new(code);
+    let err = ic.exe(None, Input::String("x"), Output::None).unwrap_err();
+    assert!(matches!(err, IntcodeError::InvalidInput { address: 0, .. }));
+}
+
+#[test]
+fn test_channel_closed() {
+    let code = io::Cursor::new("3,0,99");
+    let mut ic = Program::new(code);
+    let (tx, rx) = channel::<Int>();
+    drop(tx);
+    let err = ic.exe(None, Input::Channel(rx), Output::None).unwrap_err();
+    assert!(matches!(
+        err,
+        IntcodeError::ChannelClosed { address: 0, .. }
+    ));
+}
+
+#[test]
+fn test_run_until_io() {
+    let code = io::Cursor::new("3,9,1001,9,1,9,4,9,99,0");
+    let mut ic = Program::new(code);
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::NeedsInput];
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::NeedsInput];
+    ic.push_input(41);
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::Output(42)];
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::Halted];
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::Halted];
+}
+
+#[test]
+fn test_step() {
+    let code = io::Cursor::new("1101,2,3,0,99");
+    let mut ic = Program::new(code);
+    assert_eq![ic.ip(), 0];
+    ic.step(None, &mut Input::None, &mut Output::None)
+        .expect("execution error");
+    assert_eq![ic.ip(), 4];
+    assert_eq![ic.steps(), 1];
+    assert!(!ic.is_halted());
+    ic.step(None, &mut Input::None, &mut Output::None)
+        .expect("execution error");
+    assert_eq![ic.ip(), 4];
+    assert_eq![ic.steps(), 2];
+    assert!(ic.is_halted());
+    ic.step(None, &mut Input::None, &mut Output::None)
+        .expect("execution error");
+    assert_eq![ic.steps(), 2];
+    assert_eq![ic.peek(0).unwrap(), 5];
+    ic.reset();
+    assert_eq![ic.ip(), 0];
+    assert_eq![ic.steps(), 0];
+    assert!(!ic.is_halted());
+    assert_eq![ic.peek(0).unwrap(), 1101];
+}
+
+#[test]
+fn test_far_address() {
+    // set rel_base to 10^12 and write 7 there
+    let code = io::Cursor::new("109,1000000000000,21101,3,4,0,204,0,99");
+    let mut ic = Program::new(code);
+    let mut output = io::Cursor::new(vec![0; 2]);
+    ic.exe(None, Input::None, Output::Writer(&mut output))
+        .expect("execution error");
+    assert_eq![ic.peek(1_000_000_000_000).unwrap(), 7];
+    assert_eq![output.get_ref(), b"7\n"];
+}
+
+#[test]
+fn test_memory_limit() {
+    let code = io::Cursor::new("1101,3,4,100000,99");
+    let mut ic = Program::new(code);
+    ic.set_options(Options {
+        memory_limit: Some(100),
+        ..Options::default()
+    });
+    let err = ic.exe(None, Input::None, Output::None).unwrap_err();
+    assert!(matches!(
+        err,
+        IntcodeError::MemoryLimit {
+            address: 100000,
+            limit: 100
+        }
+    ));
+}
+
+#[test]
+fn test_vec_io() {
+    let code = io::Cursor::new("3,0,4,0,3,0,4,0,99");
+    let mut ic = Program::new(code);
+    let mut output: Vec<Int> = Vec::new();
+    ic.exe(None, VecDeque::from(vec![3, 4]), &mut output)
+        .expect("execution error");
+    assert_eq![output, vec![3, 4]];
+}
+
+#[test]
+fn test_deque_io() {
+    let code = io::Cursor::new("3,0,4,0,3,0,4,0,99");
+    let mut ic = Program::new(code);
+    let mut input: VecDeque<Int> = VecDeque::new();
+    input.push_back(5);
+    input.push_back(6);
+    let mut output: VecDeque<Int> = VecDeque::new();
+    ic.exe(None, &mut input, &mut output)
+        .expect("execution error");
+    assert!(input.is_empty());
+    assert_eq![output, vec![5, 6]];
+}
+
+#[test]
+fn test_fn_io() {
+    let code = io::Cursor::new("3,0,4,0,3,0,4,0,99");
+    let mut ic = Program::new(code);
+    let mut sum = 0;
+    ic.exe(None, IterInput(7..), FnOutput(|v| sum += v))
+        .expect("execution error");
+    assert_eq![sum, 15];
+}
+
+#[test]
+fn test_step_back() {
+    let code = io::Cursor::new("109,5,21101,2,3,0,3,20,4,20,99");
+    let mut ic = Program::new(code);
+    ic.set_options(Options {
+        history: 100,
+        ..Options::default()
+    });
+    let mut output: Vec<Int> = Vec::new();
+    ic.exe(None, VecDeque::from(vec![8]), &mut output)
+        .expect("execution error");
+    assert_eq![ic.peek(5).unwrap(), 5];
+    assert_eq![ic.peek(20).unwrap(), 8];
+    assert!(ic.is_halted());
+
+    let end = ic.step_back().unwrap();
+    assert_eq![end.address(), 10];
+    assert!(!ic.is_halted());
+    assert_eq![ic.step_back().unwrap().output(), Some(8)];
+    let input = ic.step_back().unwrap();
+    assert_eq![input.input(), Some(8)];
+    assert_eq![ic.peek(20).unwrap(), 0];
+    ic.step_back().unwrap();
+    assert_eq![ic.peek(5).unwrap(), 0];
+    assert_eq![ic.rel_base(), 5];
+    ic.step_back().unwrap();
+    assert_eq![ic.rel_base(), 0];
+    assert_eq![ic.ip(), 0];
+    assert_eq![ic.steps(), 0];
+    assert!(ic.step_back().is_none());
+
+    // running again gives the same result
+    ic.exe(None, VecDeque::from(vec![8]), &mut output)
+        .expect("execution error");
+    assert_eq![output, vec![8, 8]];
+}
+
+#[test]
+fn test_history_size() {
+    let code = io::Cursor::new("1101,1,1,0,1101,2,2,0,1101,3,3,0,99");
+    let mut ic = Program::new(code);
+    ic.set_options(Options {
+        history: 2,
+        ..Options::default()
+    });
+    ic.exe(None, Input::None, Output::None)
+        .expect("execution error");
+    assert!(ic.step_back().is_some());
+    assert!(ic.step_back().is_some());
+    assert!(ic.step_back().is_none());
+    assert_eq![ic.ip(), 8];
+    assert_eq![ic.peek(0).unwrap(), 4];
+}
+
+#[test]
+fn test_no_history() {
+    let code = io::Cursor::new("1101,1,1,0,99");
+    let mut ic = Program::new(code);
+    ic.exe(None, Input::None, Output::None)
+        .expect("execution error");
+    assert!(ic.step_back().is_none());
+}
+
+#[test]
+fn test_watchpoints() {
+    // writes 2 to [20], 4 to [21], then reads [20] and writes [22]
+    let code = io::Cursor::new("1101,1,1,20,1101,2,2,21,1001,20,5,22,99");
+    let mut ic = Program::new(code);
+    ic.watch(20, 20, Access::Write);
+    ic.watch(22, 30, Access::Read);
+    let hit = WatchHit {
+        address: 0,
+        target: 20,
+        old: 0,
+        new: 2,
+        access: Access::Write,
+    };
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Watchpoint(hit)
+    ];
+    assert_eq![ic.watch_hits(), &[hit]];
+    assert_eq![ic.ip(), 4];
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Halted
+    ];
+
+    ic.reset();
+    assert!(ic.unwatch(20, 20));
+    ic.watch(20, 21, Access::ReadWrite);
+    let mut hits = Vec::new();
+    while let RunState::Watchpoint(_) = ic.exe(None, Input::None, Output::None).unwrap() {
+        hits.extend(ic.watch_hits().iter().map(|h| h.to_string()));
+    }
+    assert_eq![
+        hits,
+        vec![
+            "0: write [20] 0 -> 2",
+            "4: write [21] 0 -> 4",
+            "8: read [20] = 2"
+        ]
+    ];
+}
+
+#[test]
+fn test_watch_output() {
+    // OUT [5] reads a watched cell; the output follows the watchpoint
+    let mut ic = Program::from_code(vec![4, 5, 104, 9, 99, 42]);
+    ic.watch(5, 5, Access::Read);
+    assert![matches![
+        ic.run_until_io(None).unwrap(),
+        RunState::Watchpoint(WatchHit { target: 5, .. })
+    ]];
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::Output(42)];
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::Output(9)];
+    assert_eq![ic.run_until_io(None).unwrap(), RunState::Halted];
+}
+
+#[test]
+fn test_tracer() {
+    let mut ic = Program::from_code(vec![1101, 2, 3, 5, 99, 0]);
+    let mut events: Vec<TraceEvent> = Vec::new();
+    let mut record = |e: &TraceEvent| events.push(e.clone());
+    ic.exe(Some(&mut record), Input::None, Output::None)
+        .unwrap();
+    assert_eq![events.len(), 2];
+    assert_eq![events[0].mnemonic, "ADD"];
+    assert_eq![events[0].write, Some((5, 5))];
+    assert_eq![
+        events[0].params.iter().map(|p| p.value).collect::<Vec<_>>(),
+        vec![Some(2), Some(3), None]
+    ];
+    assert_eq![(events[1].step, events[1].address), (1, 4)];
+}
+
+#[test]
+fn test_self_modifying() {
+    // OUT #7; ADD #0, #4, [0]; JZ #0, #0 -- the ADD turns the OUT into
+    // OUT [7], which outputs the 0 at address 7
+    let code = vec![104, 7, 1101, 0, 4, 0, 1106, 0, 0];
+    let run = |options| {
+        let mut ic = Program::from_code(code.clone());
+        ic.set_options(options);
+        assert_eq![ic.run_until_io(None).unwrap(), RunState::Output(7)];
+        assert_eq![ic.run_until_io(None).unwrap(), RunState::Output(0)];
+    };
+    run(Options::default());
+    #[cfg(feature = "uncached")]
+    run(Options {
+        uncached: true,
+        ..Options::default()
+    });
+}
+
+#[test]
+fn test_poke_invalidates() {
+    let mut ic = Program::from_code(vec![104, 5, 99]);
+    let mut output = Vec::new();
+    ic.step(None, &mut Input::None, &mut output).unwrap();
+    ic.set_ip(0);
+    ic.poke(0, 4).unwrap();
+    ic.step(None, &mut Input::None, &mut output).unwrap();
+    assert_eq![output, vec![5, 0]];
+}
+
+#[test]
+fn test_fuel() {
+    // count down from 3, outputting each value
+    let mut ic = Program::from_code(vec![
+        1101, 3, 0, 20, 4, 20, 1001, 20, -1, 20, 1005, 20, 4, 99,
+    ]);
+    ic.set_options(Options {
+        fuel: Some(4),
+        ..Options::default()
+    });
+    let mut output = Vec::new();
+    assert_eq![
+        ic.exe(None, Input::None, &mut output).unwrap(),
+        RunState::BudgetExhausted
+    ];
+    assert_eq![(ic.ip(), ic.steps()), (4, 4)];
+    assert_eq![output, vec![3]];
+    // each call gets a fresh budget
+    while ic.exe(None, Input::None, &mut output).unwrap() == RunState::BudgetExhausted {}
+    assert!(ic.is_halted());
+    assert_eq![output, vec![3, 2, 1]];
+}
+
+#[test]
+fn test_cancel() {
+    // JNZ #1, #0 loops forever
+    let mut ic = Program::from_code(vec![1105, 1, 0]);
+    let cancel = Cancel::new();
+    ic.set_options(Options {
+        cancel: Some(cancel.clone()),
+        ..Options::default()
+    });
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        cancel.cancel();
+        cancel
+    });
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Cancelled
+    ];
+    let steps = ic.steps();
+    assert![steps > 0];
+    let cancel = canceller.join().unwrap();
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Cancelled
+    ];
+    assert_eq![ic.steps(), steps];
+    // clearing the handle resumes the program
+    cancel.clear();
+    ic.set_options(Options {
+        fuel: Some(10),
+        ..ic.options().clone()
+    });
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::BudgetExhausted
+    ];
+    assert_eq![ic.steps(), steps + 10];
+}
+
+#[test]
+fn test_detect_loops() {
+    let detect = Options {
+        detect_loops: true,
+        ..Options::default()
+    };
+    // JZ #0, #6; JNZ #1, #0; JNZ #1, #3 -- loops through all three
+    let mut ic = Program::from_code(vec![1106, 0, 6, 1105, 1, 0, 1105, 1, 3]);
+    ic.set_options(detect.clone());
+    match ic.exe(None, Input::None, Output::None) {
+        Err(e @ IntcodeError::InfiniteLoop { .. }) => assert_eq![
+            e.to_string(),
+            "6: instruction 1105 repeats an earlier state; infinite loop in 0-6"
+        ],
+        r => panic!["unexpected result {:?}", r],
+    }
+    assert_eq![ic.ip(), 6];
+
+    // a loop counting down from 3 ends
+    let mut ic = Program::from_code(vec![1101, 3, 0, 20, 1001, 20, -1, 20, 1005, 20, 4, 99]);
+    ic.set_options(detect.clone());
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Halted
+    ];
+
+    // a loop with output in it repeats its state, but isn't stuck
+    let mut ic = Program::from_code(vec![104, 1, 1105, 1, 0]);
+    ic.set_options(Options {
+        fuel: Some(100),
+        ..detect.clone()
+    });
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::BudgetExhausted
+    ];
+
+    // neither is a loop incrementing a counter forever
+    let mut ic = Program::from_code(vec![1001, 7, 1, 7, 1105, 1, 0, 0]);
+    ic.set_options(Options {
+        fuel: Some(100),
+        ..detect.clone()
+    });
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::BudgetExhausted
+    ];
+
+    // stepping back returns to states seen on the way, which aren't a loop
+    let countdown = vec![1101, 3, 0, 20, 1001, 20, -1, 20, 1005, 20, 4, 99];
+    let mut ic = Program::from_code(countdown.clone());
+    ic.set_options(Options {
+        history: 100,
+        ..detect.clone()
+    });
+    for _ in 0..6 {
+        ic.step(None, &mut Input::None, &mut Output::None).unwrap();
+    }
+    for _ in 0..3 {
+        ic.step_back().unwrap();
+    }
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Halted
+    ];
+
+    // nor does restarting the loop by hand
+    let mut ic = Program::from_code(countdown);
+    ic.set_options(detect);
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Halted
+    ];
+    ic.poke(20, 3).unwrap();
+    ic.set_ip(4);
+    assert_eq![
+        ic.exe(None, Input::None, Output::None).unwrap(),
+        RunState::Halted
+    ];
+}
+
+#[test]
+fn test_overflow() {
+    let max = Int::MAX;
+    let run = |code: Vec<Int>, overflow: Overflow| {
+        let mut ic = Program::from_code(code);
+        ic.set_options(Options {
+            overflow,
+            ..Options::default()
+        });
+        let mut output = Vec::new();
+        let result = ic.exe(None, Input::None, &mut output).map(|_| ());
+        (ic, result.map_err(|e| e.to_string()), output)
+    };
+
+    // ADD #max, #1, [5]
+    let add = vec![1101, max, 1, 5, 99, 0];
+    assert_eq![
+        run(add.clone(), Overflow::Error).1,
+        Err("0: instruction 1101 overflows computing 9223372036854775807 + 1".to_string())
+    ];
+    assert_eq![
+        run(add.clone(), Overflow::Wrapping).0.peek(5).unwrap(),
+        Int::MIN
+    ];
+    assert_eq![run(add, Overflow::Saturating).0.peek(5).unwrap(), max];
+
+    // MUL #max, #-2, [5]
+    let mul = vec![1102, max, -2, 5, 99, 0];
+    assert_eq![
+        run(mul.clone(), Overflow::Error).1,
+        Err("0: instruction 1102 overflows computing 9223372036854775807 * -2".to_string())
+    ];
+    assert_eq![run(mul.clone(), Overflow::Wrapping).0.peek(5).unwrap(), 2];
+    assert_eq![run(mul, Overflow::Saturating).0.peek(5).unwrap(), Int::MIN];
+
+    // REL #max; REL #1
+    let rel = vec![109, max, 109, 1, 99];
+    let (ic, result, _) = run(rel.clone(), Overflow::Error);
+    assert_eq![
+        result,
+        Err("2: instruction 109 overflows computing 9223372036854775807 + 1".to_string())
+    ];
+    assert_eq![(ic.ip(), ic.rel_base()), (2, max)];
+    assert_eq![run(rel.clone(), Overflow::Wrapping).0.rel_base(), Int::MIN];
+    assert_eq![run(rel, Overflow::Saturating).0.rel_base(), max];
+
+    // addresses that overflow are an error whatever the policy
+    for &overflow in &[Overflow::Error, Overflow::Wrapping, Overflow::Saturating] {
+        // REL #max; OUT rb+1
+        let relative = vec![109, max, 204, 1, 99];
+        assert_eq![
+            run(relative, overflow).1,
+            Err("2: instruction 204 overflows computing 1 + 9223372036854775807".to_string())
+        ];
+        // REL #max; ADD #5, #6, [rb+1]; OUT rb+1
+        let relative = vec![109, max, 21101, 5, 6, 1, 204, 1, 99];
+        let (ic, result, output) = run(relative, overflow);
+        assert_eq![
+            (ic.ip(), result, output),
+            (
+                2,
+                Err("2: instruction 21101 overflows computing 1 + 9223372036854775807".to_string()),
+                vec![]
+            )
+        ];
+    }
+
+    // OUT #7 at the end of memory, so the next instruction is out of range
+    let end = |overflow| {
+        let mut ic = Program::from_code(vec![]);
+        ic.set_options(Options {
+            overflow,
+            ..Options::default()
+        });
+        ic.poke(max - 1, 104).unwrap();
+        ic.poke(max, 7).unwrap();
+        ic.set_ip(max - 1);
+        let mut output = Vec::new();
+        let result = ic.step(None, &mut Input::None, &mut output);
+        (ic.ip(), result.map_err(|e| e.to_string()), output)
+    };
+    for &overflow in &[Overflow::Error, Overflow::Wrapping, Overflow::Saturating] {
+        assert_eq![
+            end(overflow),
+            (
+                max - 1,
+                Err("9223372036854775806: instruction 104 overflows computing \
+                     9223372036854775806 + 2"
+                    .to_string()),
+                vec![]
+            )
+        ];
+    }
+
+    // an instruction ending at max, traced and profiled
+    let last = |code: &[Int], overflow| {
+        let mut ic = Program::from_code(vec![]);
+        ic.set_options(Options {
+            overflow,
+            profile: true,
+            ..Options::default()
+        });
+        for (i, v) in code.iter().enumerate() {
+            ic.poke(max - 2 + i as Int, *v).unwrap();
+        }
+        ic.set_ip(max - 2);
+        let mut traced = 0;
+        let mut tracer = |_: &TraceEvent| traced += 1;
+        let result = ic.step(Some(&mut tracer), &mut Input::None, &mut Output::None);
+        (ic.ip(), result.map_err(|e| e.to_string()), traced)
+    };
+    let past = |n| {
+        Err(format![
+            "9223372036854775805: instruction {} overflows computing 9223372036854775805 + 3",
+            n
+        ])
+    };
+    for &overflow in &[Overflow::Error, Overflow::Wrapping, Overflow::Saturating] {
+        // ADD #1, #2, [max + 1]
+        assert_eq![last(&[1101, 1, 2], overflow), (max - 2, past(1101), 0)];
+        // JNZ #0, #0 falls through past max
+        assert_eq![last(&[1105, 0, 0], overflow), (max - 2, past(1105), 0)];
+        // JNZ #1, #0 jumps
+        assert_eq![last(&[1105, 1, 0], overflow), (0, Ok(()), 1)];
+    }
+}
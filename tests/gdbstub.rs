@@ -0,0 +1,134 @@
+extern crate intcode;
+
+use intcode::gdbstub::GdbStub;
+use intcode::Program;
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+struct Client {
+    stream: io::BufReader<TcpStream>,
+}
+
+impl Client {
+    fn read_packet(&mut self) -> String {
+        let mut data = Vec::new();
+        self.stream.read_until(b'$', &mut data).unwrap();
+        data.clear();
+        self.stream.read_until(b'#', &mut data).unwrap();
+        data.pop();
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        let sum = data.iter().fold(0u8, |s, b| s.wrapping_add(*b));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!["{:02x}", sum]
+        );
+        self.stream.get_mut().write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    /// Send a packet and return the reply.
+    fn send(&mut self, data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+        write!(self.stream.get_mut(), "${}#{:02x}", data, sum).unwrap();
+        let mut ack = [0];
+        self.stream.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+        self.read_packet()
+    }
+}
+
+fn connect(code: &str, input: &[i64]) -> (Client, thread::JoinHandle<Program>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let prog = Program::new(io::Cursor::new(code.to_string()));
+    let input = input.to_vec();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(prog, &input);
+        stub.serve(stream).unwrap();
+        stub.program().clone()
+    });
+    let stream = TcpStream::connect(addr).unwrap();
+    (
+        Client {
+            stream: io::BufReader::new(stream),
+        },
+        server,
+    )
+}
+
+#[test]
+fn test_session() {
+    let (mut c, server) = connect("3,11,1001,11,1,11,4,11,104,-1,99,0", &[5]);
+    assert!(c.send("qSupported:swbreak+").contains("PacketSize"));
+    assert_eq!(c.send("?"), "S05");
+    assert_eq!(c.send("g"), "0".repeat(32));
+    // cell 6 is at byte address 0x30
+    assert_eq!(c.send("Z0,30,1"), "OK");
+    assert_eq!(c.send("c"), "S05");
+    assert_eq!(c.send("p0"), "3000000000000000");
+    assert_eq!(c.send("m58,8"), "0600000000000000");
+    assert_eq!(c.send("M58,1:07"), "OK");
+    // output is sent as console output before the stop reply
+    assert_eq!(c.send("s"), "O370a");
+    assert_eq!(c.read_packet(), "S05");
+    assert_eq!(c.send("z0,30,1"), "OK");
+    assert_eq!(c.send("P0=3000000000000000"), "OK");
+    assert_eq!(c.send("c"), "O370a");
+    assert_eq!(c.read_packet(), "O2d310a");
+    assert_eq!(c.read_packet(), "W00");
+    assert_eq!(c.send("vMustReplyEmpty"), "");
+    assert_eq!(c.send("D"), "OK");
+    let prog = server.join().unwrap();
+    assert!(prog.is_halted());
+    assert_eq!(prog.peek(11).unwrap(), 7);
+}
+
+#[test]
+fn test_interrupt() {
+    let (mut c, server) = connect("1105,1,0", &[]);
+    c.stream.get_mut().write_all(b"$c#63").unwrap();
+    let mut ack = [0];
+    c.stream.read_exact(&mut ack).unwrap();
+    c.stream.get_mut().write_all(&[3]).unwrap();
+    assert_eq!(c.read_packet(), "S02");
+    assert_eq!(c.send("m0,10"), "51040000000000000100000000000000");
+    c.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn test_oversized_read() {
+    let (mut c, server) = connect("99", &[]);
+    // replies are limited to 2048 bytes, each sent as two hex digits
+    let reply = c.send("m0,7fffffffffffffff");
+    assert_eq!(reply.len(), 2 * 2048);
+    assert!(reply.starts_with("6300000000000000"));
+    assert_eq!(c.send("mffffffffffffffff,2"), "E00");
+    c.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn test_input_exhausted() {
+    let (mut c, server) = connect("3,5,3,6,99", &[1]);
+    // running out of input isn't a fault, so it stops with SIGTRAP
+    let message = c.send("c");
+    assert!(message.starts_with('O'));
+    let text: Vec<u8> = (1..message.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&message[i..i + 2], 16).unwrap())
+        .collect();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "2: instruction 3 requires input but none is available\n"
+    );
+    assert_eq!(c.read_packet(), "S05");
+    assert_eq!(c.send("p0"), "1000000000000000");
+    c.send("k");
+    let prog = server.join().unwrap();
+    assert_eq!(prog.peek(5).unwrap(), 1);
+}